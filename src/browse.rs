@@ -0,0 +1,652 @@
+//! Interactive browser of the tree, `wims browse`, similar to ncdu.
+//!
+//! `Browser` keeps the navigation state and renders into plain lines of text, the terminal
+//! part only switches the terminal to raw mode, draws the lines and feeds keys back. Tests
+//! drive `Browser` with keys and check the rendered lines without any terminal.
+
+use libc;
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::{self, Read, Write};
+use std::mem;
+
+use types::event_type::EventType;
+use types::formatter::{bar, human_format_if_needed};
+use types::graph_style::GraphStyle;
+use types::options::{Options, OptionsSort};
+use types::path_cache::{PathCache, PathCacheInfo};
+use types::sort_by::SortBy;
+
+const BAR_WIDTH: usize = 10;
+
+const HELP: &'static str = "up/down move  right/enter open  left back  s/n/m/c sort  f counts  \
+                            b largest  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Backspace,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Esc,
+    Char(char),
+}
+
+/// Splits input read from terminal into keys, unknown escape sequences are dropped
+pub fn parse_keys(buf: &[u8]) -> Vec<Key> {
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < buf.len() {
+        match buf[i] {
+            0x1b if i + 2 < buf.len() && (buf[i + 1] == b'[' || buf[i + 1] == b'O') => {
+                // CSI or SS3 sequence, parameters end with the final byte
+                let mut end = i + 2;
+                while end < buf.len() && ((buf[end] as char).is_ascii_digit() || buf[end] == b';') {
+                    end += 1;
+                }
+                if end >= buf.len() {
+                    break;
+                }
+
+                let key = match (&buf[i + 2..end], buf[end]) {
+                    (_, b'A') => Some(Key::Up),
+                    (_, b'B') => Some(Key::Down),
+                    (_, b'C') => Some(Key::Right),
+                    (_, b'D') => Some(Key::Left),
+                    (_, b'H') | (b"1", b'~') | (b"7", b'~') => Some(Key::Home),
+                    (_, b'F') | (b"4", b'~') | (b"8", b'~') => Some(Key::End),
+                    (b"5", b'~') => Some(Key::PageUp),
+                    (b"6", b'~') => Some(Key::PageDown),
+                    _ => None,
+                };
+                res.extend(key);
+                i = end + 1;
+                continue;
+            }
+            // Signals are disabled in raw mode, so Ctrl-C quits like Esc
+            0x1b | 0x03 => res.push(Key::Esc),
+            b'\r' | b'\n' => res.push(Key::Enter),
+            0x7f | 0x08 => res.push(Key::Backspace),
+            c if c >= 0x20 && c < 0x7f => res.push(Key::Char(c as char)),
+            _ => {}
+        }
+        i += 1;
+    }
+    res
+}
+
+#[derive(Debug, Clone)]
+struct Entry<'a> {
+    name: String,
+    /// Keys from the top of the tree
+    keys: Vec<OsString>,
+    node: &'a PathCacheInfo,
+    is_dir: bool,
+}
+
+// Listed directory, `None` stands for the list of scanned roots
+#[derive(Debug, Clone)]
+struct Level {
+    dir: Option<Vec<OsString>>,
+    title: String,
+    selected: usize,
+}
+
+/// Lines of one screen, `highlight` is the index of the line with selected entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screen {
+    pub lines: Vec<String>,
+    pub highlight: Option<usize>,
+}
+
+pub struct Browser<'a> {
+    pc: &'a BTreeMap<OsString, PathCacheInfo>,
+    sort: OptionsSort,
+    graph_style: GraphStyle,
+    show_counts: bool,
+    level: Level,
+    /// Levels above the current one, to restore selection when going back
+    parents: Vec<Level>,
+    entries: Vec<Entry<'a>>,
+    offset: usize,
+}
+
+impl<'a> Browser<'a> {
+    /// Starts in the scanned root, or in the list of roots when there are more of them
+    pub fn new(pc: &'a BTreeMap<OsString, PathCacheInfo>,
+               sort: OptionsSort,
+               graph_style: GraphStyle)
+               -> Browser<'a> {
+        let mut browser = Browser {
+            pc: pc,
+            sort: sort,
+            graph_style: graph_style,
+            show_counts: true,
+            level: Level {
+                dir: None,
+                title: String::from("All roots"),
+                selected: 0,
+            },
+            parents: Vec::new(),
+            entries: Vec::new(),
+            offset: 0,
+        };
+
+        browser.load();
+        if browser.entries.len() == 1 && browser.entries[0].is_dir {
+            browser.open();
+        }
+        browser
+    }
+
+    /// Handles a key, returns false when the browser should quit. `page` is the number of
+    /// entries on screen.
+    pub fn handle(&mut self, key: Key, page: usize) -> bool {
+        let last = self.entries.len().saturating_sub(1);
+        let page = page.max(1);
+
+        match key {
+            Key::Up | Key::Char('k') => self.select(self.level.selected.saturating_sub(1)),
+            Key::Down | Key::Char('j') => self.select(self.level.selected + 1),
+            Key::PageUp => self.select(self.level.selected.saturating_sub(page)),
+            Key::PageDown => self.select(self.level.selected + page),
+            Key::Home => self.select(0),
+            Key::End => self.select(last),
+            Key::Right | Key::Enter | Key::Char('l') => self.open(),
+            Key::Left | Key::Backspace | Key::Char('h') => self.back(),
+            Key::Char('s') => self.sort_by(SortBy::Size),
+            Key::Char('n') => self.sort_by(SortBy::Name),
+            Key::Char('m') => self.sort_by(SortBy::Mtime),
+            Key::Char('c') => self.sort_by(SortBy::Count),
+            Key::Char('f') => self.show_counts = !self.show_counts,
+            Key::Char('b') => self.largest(),
+            Key::Char('q') | Key::Esc => return false,
+            _ => {}
+        }
+
+        true
+    }
+
+    /// Renders exactly `height` lines of `width` characters
+    pub fn render(&mut self, width: usize, height: usize) -> Screen {
+        let rows = height.saturating_sub(2);
+        if self.level.selected < self.offset {
+            self.offset = self.level.selected;
+        } else if rows > 0 && self.level.selected >= self.offset + rows {
+            self.offset = self.level.selected + 1 - rows;
+        }
+
+        let total = self.total();
+        let mut lines = Vec::with_capacity(height);
+        lines.push(format!(" {}  {}", self.level.title, human_format_if_needed(total, true)));
+
+        let mut highlight = None;
+        for (i, entry) in self.entries.iter().enumerate().skip(self.offset).take(rows) {
+            if i == self.level.selected {
+                highlight = Some(lines.len());
+            }
+
+            let fraction = if total > 0 {
+                entry.node.total_size() as f64 / total as f64
+            } else {
+                0.0
+            };
+            let counts = match (self.show_counts, entry.is_dir) {
+                (false, _) => String::new(),
+                (true, true) => format!("{:>9} ", entry.node.items()),
+                (true, false) => " ".repeat(10),
+            };
+
+            lines.push(format!("{:>10} {:>5.1}% [{}] {}{}{}",
+                               human_format_if_needed(entry.node.total_size(), true),
+                               fraction * 100.0,
+                               bar(fraction, BAR_WIDTH, self.graph_style),
+                               counts,
+                               entry.name,
+                               if entry.is_dir { "/" } else { "" }));
+        }
+
+        if self.entries.is_empty() && rows > 0 {
+            lines.push(String::from(" (empty)"));
+        }
+        while lines.len() + 1 < height {
+            lines.push(String::new());
+        }
+
+        lines.push(format!(" {} items  sort: {}{}  {}",
+                           self.entries.len(),
+                           sort_name(self.sort.by),
+                           if self.sort.reverse { " reversed" } else { "" },
+                           HELP));
+        lines.truncate(height);
+
+        Screen {
+            lines: lines.into_iter().map(|line| fit(&line, width)).collect(),
+            highlight: highlight,
+        }
+    }
+
+    fn select(&mut self, index: usize) {
+        self.level.selected = index.min(self.entries.len().saturating_sub(1));
+    }
+
+    fn open(&mut self) {
+        let entry = match self.entries.get(self.level.selected) {
+            Some(entry) if entry.is_dir => entry.clone(),
+            _ => return,
+        };
+
+        let title = match self.level.dir {
+            Some(_) => format!("{}/{}", self.level.title.trim_right_matches('/'), entry.name),
+            None => entry.name.clone(),
+        };
+        let level = mem::replace(&mut self.level,
+                                 Level {
+                                     dir: Some(entry.keys),
+                                     title: title,
+                                     selected: 0,
+                                 });
+        self.parents.push(level);
+        self.offset = 0;
+        self.load();
+    }
+
+    fn back(&mut self) {
+        if let Some(level) = self.parents.pop() {
+            self.level = level;
+            self.offset = 0;
+            self.load();
+        }
+    }
+
+    // Selects the largest entry, when it is selected already goes into it
+    fn largest(&mut self) {
+        let index = self.entries
+            .iter()
+            .enumerate()
+            .max_by_key(|&(i, entry)| (entry.node.total_size(), -(i as i64)))
+            .map(|(i, _)| i);
+
+        match index {
+            Some(i) if i == self.level.selected => self.open(),
+            Some(i) => self.select(i),
+            None => {}
+        }
+    }
+
+    // Same key again reverses the order
+    fn sort_by(&mut self, by: SortBy) {
+        if self.sort.by == by {
+            self.sort.reverse = !self.sort.reverse;
+        } else {
+            self.sort = OptionsSort {
+                by: by,
+                reverse: false,
+            };
+        }
+
+        let selected = self.entries.get(self.level.selected).map(|entry| entry.keys.clone());
+        self.sort_entries();
+        if let Some(keys) = selected {
+            self.level.selected = self.entries.iter().position(|entry| entry.keys == keys).unwrap_or(0);
+        }
+    }
+
+    fn load(&mut self) {
+        let pc = self.pc;
+        let mut entries = Vec::new();
+        match self.level.dir {
+            Some(ref keys) => {
+                if let Some(childs) = find(pc, keys).and_then(|v| v.childs.as_ref()) {
+                    for (key, v) in childs.iter() {
+                        let mut child_keys = keys.clone();
+                        child_keys.push(key.clone());
+                        entries.extend(entry(v, v.path.to_string_lossy().into_owned(), child_keys));
+                    }
+                }
+            }
+            None => roots(pc, "", &Vec::new(), &mut entries),
+        }
+
+        self.entries = entries;
+        self.sort_entries();
+        let selected = self.level.selected;
+        self.select(selected);
+    }
+
+    fn sort_entries(&mut self) {
+        let by = self.sort.by;
+        self.entries.sort_by(|a, b| PathCache::compare(a.node, b.node, by));
+
+        if self.sort.reverse {
+            self.entries.reverse();
+        }
+    }
+
+    fn total(&self) -> u64 {
+        match self.level.dir {
+            Some(ref keys) => find(self.pc, keys).map_or(0, |v| v.total_size()),
+            None => self.entries.iter().map(|entry| entry.node.total_size()).sum(),
+        }
+    }
+}
+
+// Path prefixes above the scanned roots become part of the root names
+fn roots<'a>(pc: &'a BTreeMap<OsString, PathCacheInfo>,
+             prefix: &str,
+             keys: &Vec<OsString>,
+             res: &mut Vec<Entry<'a>>) {
+    for (key, v) in pc.iter() {
+        let name = key.to_string_lossy();
+        let path = if prefix.is_empty() && name.is_empty() {
+            String::from("/")
+        } else if prefix.is_empty() || prefix.ends_with('/') {
+            format!("{}{}", prefix, name)
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        let mut keys = keys.clone();
+        keys.push(key.clone());
+        match v.data {
+            Some(_) => res.extend(entry(v, path, keys)),
+            None => {
+                if let Some(ref childs) = v.childs {
+                    roots(childs, &path, &keys, res);
+                }
+            }
+        }
+    }
+}
+
+fn entry<'a>(v: &'a PathCacheInfo, name: String, keys: Vec<OsString>) -> Option<Entry<'a>> {
+    let data = v.data?;
+    let is_dir = match data.event_type {
+        EventType::DirEnter => true,
+        EventType::DirLeave => return None,
+        _ => false,
+    };
+
+    Some(Entry {
+        name: name,
+        keys: keys,
+        node: v,
+        is_dir: is_dir,
+    })
+}
+
+fn find<'a>(pc: &'a BTreeMap<OsString, PathCacheInfo>, keys: &[OsString]) -> Option<&'a PathCacheInfo> {
+    let (last, parents) = keys.split_last()?;
+    let mut level = pc;
+    for key in parents.iter() {
+        level = level.get(key)?.childs.as_ref()?;
+    }
+    level.get(last)
+}
+
+fn sort_name(by: SortBy) -> &'static str {
+    match by {
+        SortBy::Name => "name",
+        SortBy::Size => "size",
+        SortBy::Mtime => "mtime",
+        SortBy::Count => "count",
+    }
+}
+
+// Cuts or pads line to exactly `width` characters, control characters in names are replaced
+fn fit(line: &str, width: usize) -> String {
+    let mut res = line.chars()
+        .map(|c| if c.is_control() { '?' } else { c })
+        .take(width)
+        .collect::<String>();
+    let len = res.chars().count();
+    res.push_str(&" ".repeat(width - len));
+    res
+}
+
+/// Browses the tree in the terminal until the user quits
+pub fn run(pc: &BTreeMap<OsString, PathCacheInfo>, opts: &Options) -> io::Result<()> {
+    let mut browser = Browser::new(pc, opts.sort, opts.tree.graph_style);
+
+    let _terminal = RawTerminal::enable()?;
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let mut buf = [0; 64];
+    loop {
+        let (width, height) = terminal_size();
+        draw(&mut out, &browser.render(width, height))?;
+
+        let len = input.read(&mut buf)?;
+        if len == 0 {
+            return Ok(());
+        }
+        for key in parse_keys(&buf[..len]) {
+            if !browser.handle(key, height.saturating_sub(2)) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn draw<W: Write>(out: &mut W, screen: &Screen) -> io::Result<()> {
+    write!(out, "\x1b[H")?;
+    for (i, line) in screen.lines.iter().enumerate() {
+        if i > 0 {
+            write!(out, "\r\n")?;
+        }
+        if screen.highlight == Some(i) {
+            write!(out, "\x1b[7m{}\x1b[0m", line)?;
+        } else {
+            write!(out, "{}", line)?;
+        }
+    }
+    out.flush()
+}
+
+fn terminal_size() -> (usize, usize) {
+    unsafe {
+        let mut size: libc::winsize = mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 &&
+           size.ws_row > 0 {
+            (size.ws_col as usize, size.ws_row as usize)
+        } else {
+            (80, 24)
+        }
+    }
+}
+
+// Raw mode on alternate screen, the original terminal is restored when dropped
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    fn enable() -> io::Result<RawTerminal> {
+        let original = unsafe {
+            let mut original: libc::termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            original
+        };
+
+        let mut out = io::stdout();
+        write!(out, "\x1b[?1049h\x1b[?25l\x1b[2J")?;
+        out.flush()?;
+
+        Ok(RawTerminal { original: original })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        let _ = write!(out, "\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = out.flush();
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::path_cache::fixture;
+
+    fn tree() -> BTreeMap<OsString, PathCacheInfo> {
+        fixture::tree(&[("/r/s", EventType::DirEnter, 0),
+                        ("/r/s/a", EventType::DirEnter, 0),
+                        ("/r/s/a/x", EventType::File, 100),
+                        ("/r/s/a/y", EventType::File, 200),
+                        ("/r/s/b", EventType::DirEnter, 0),
+                        ("/r/s/b/z", EventType::File, 500),
+                        ("/r/s/w", EventType::File, 200)])
+    }
+
+    fn browser(pc: &BTreeMap<OsString, PathCacheInfo>) -> Browser {
+        Browser::new(pc,
+                     OptionsSort {
+                         by: SortBy::Size,
+                         reverse: false,
+                     },
+                     GraphStyle::Ascii)
+    }
+
+    // Names of listed entries, the selected one marked with `*`
+    fn names(browser: &mut Browser) -> Vec<String> {
+        let screen = browser.render(60, 10);
+        let highlight = screen.highlight;
+        screen.lines
+            .iter()
+            .enumerate()
+            .skip(1)
+            .take_while(|&(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let name = line.rsplit(' ').find(|word| !word.is_empty()).unwrap().to_string();
+                if Some(i) == highlight { format!("*{}", name) } else { name }
+            })
+            .collect()
+    }
+
+    fn press(browser: &mut Browser, keys: &[Key]) {
+        for &key in keys.iter() {
+            assert!(browser.handle(key, 8));
+        }
+    }
+
+    #[test]
+    fn render() {
+        let pc = tree();
+        let mut browser = browser(&pc);
+        let screen = browser.render(60, 6);
+
+        assert_eq!(screen.lines,
+                   vec![" /r/s  1000B                                                ",
+                        "      500B  50.0% [#####     ]         1 b/                 ",
+                        "      300B  30.0% [###       ]         2 a/                 ",
+                        "      200B  20.0% [##        ]           w                  ",
+                        "                                                            ",
+                        " 3 items  sort: size  up/down move  right/enter open  left b"]);
+        assert_eq!(screen.highlight, Some(1));
+
+        press(&mut browser, &[Key::Char('f')]);
+        assert_eq!(browser.render(60, 6).lines[1].trim_right(),
+                   "      500B  50.0% [#####     ] b/");
+    }
+
+    #[test]
+    fn navigation() {
+        let pc = tree();
+        let mut browser = browser(&pc);
+        assert_eq!(names(&mut browser), vec!["*b/", "a/", "w"]);
+
+        press(&mut browser, &[Key::Down, Key::Right]);
+        assert_eq!(names(&mut browser), vec!["*y", "x"]);
+        assert!(browser.render(60, 10).lines[0].starts_with(" /r/s/a  300B"));
+
+        // Going back selects the directory we came from
+        press(&mut browser, &[Key::Left]);
+        assert_eq!(names(&mut browser), vec!["b/", "*a/", "w"]);
+
+        press(&mut browser, &[Key::End, Key::Enter]);
+        assert_eq!(names(&mut browser), vec!["b/", "a/", "*w"]);
+
+        // List of roots above the only root
+        press(&mut browser, &[Key::Left]);
+        assert_eq!(names(&mut browser), vec!["*/r/s/"]);
+        press(&mut browser, &[Key::Left, Key::Up]);
+        assert_eq!(names(&mut browser), vec!["*/r/s/"]);
+
+        assert!(!browser.handle(Key::Char('q'), 8));
+    }
+
+    #[test]
+    fn sorting_and_largest() {
+        let pc = tree();
+        let mut browser = browser(&pc);
+
+        press(&mut browser, &[Key::Down, Key::Char('n')]);
+        assert_eq!(names(&mut browser), vec!["*a/", "b/", "w"]);
+        press(&mut browser, &[Key::Char('n')]);
+        assert_eq!(names(&mut browser), vec!["w", "b/", "*a/"]);
+        assert!(browser.render(60, 10).lines[9].contains("sort: name reversed"));
+        press(&mut browser, &[Key::Char('c')]);
+        assert_eq!(names(&mut browser), vec!["*a/", "b/", "w"]);
+
+        // First selects the largest child, then goes into it
+        press(&mut browser, &[Key::Char('b')]);
+        assert_eq!(names(&mut browser), vec!["a/", "*b/", "w"]);
+        press(&mut browser, &[Key::Char('b')]);
+        assert_eq!(names(&mut browser), vec!["*z"]);
+    }
+
+    #[test]
+    fn scrolling() {
+        let pc = tree();
+        let mut browser = browser(&pc);
+
+        press(&mut browser, &[Key::PageDown]);
+        let screen = browser.render(60, 4);
+        assert_eq!(screen.lines.len(), 4);
+        assert!(screen.lines[1].contains(" a/") && screen.lines[2].contains(" w "));
+        assert_eq!(screen.highlight, Some(2));
+
+        press(&mut browser, &[Key::Home]);
+        assert_eq!(browser.render(60, 4).highlight, Some(1));
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(parse_keys(b"\x1b[A\x1b[B\x1bOC\x1b[D\x1b[5~\x1b[6~\x1b[H\x1b[4~"),
+                   vec![Key::Up,
+                        Key::Down,
+                        Key::Right,
+                        Key::Left,
+                        Key::PageUp,
+                        Key::PageDown,
+                        Key::Home,
+                        Key::End]);
+        assert_eq!(parse_keys(b"jq\r\x7f\x1b\x03\x1b[99X"),
+                   vec![Key::Char('j'), Key::Char('q'), Key::Enter, Key::Backspace, Key::Esc, Key::Esc]);
+    }
+}
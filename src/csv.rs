@@ -0,0 +1,164 @@
+//! Flat listing of the tree with one node per row, for spreadsheets.
+//!
+//! CSV fields are quoted as described by RFC 4180 when they contain separator, quote or line
+//! break. TSV fields can not be quoted, so tab, line breaks and backslash are escaped as `\t`,
+//! `\n`, `\r` and `\\`. Paths are escaped like in the text output instead, bytes which are not
+//! valid UTF-8 and control characters as `\xNN` and backslash as `\\`.
+
+use std::io::{self, Write};
+
+use json::JsonNode;
+use types::formatter::escape_os_str;
+
+const HEADER: &'static [&'static str] = &["path",
+                                          "type",
+                                          "depth",
+                                          "own_size",
+                                          "files_size",
+                                          "dirs_size",
+                                          "total_size",
+                                          "files",
+                                          "mtime",
+                                          "ino"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Separator {
+    Comma,
+    Tab,
+}
+
+/// Writes header and every node of `roots`, parents first
+pub fn write<W: Write>(out: &mut W, roots: &Vec<JsonNode>, separator: Separator) -> io::Result<()> {
+    write_row(out,
+              HEADER[0],
+              &HEADER[1..].iter().map(|name| name.to_string()).collect(),
+              separator)?;
+    write_nodes(out, roots, separator)
+}
+
+fn write_nodes<W: Write>(out: &mut W, nodes: &Vec<JsonNode>, separator: Separator) -> io::Result<()> {
+    for node in nodes.iter() {
+        write_row(out,
+                  &escape_os_str(node.os_path.as_os_str()),
+                  &vec![node.kind.to_string(),
+                        node.depth.to_string(),
+                        node.own_size.to_string(),
+                        node.files_size.to_string(),
+                        node.dirs_size.to_string(),
+                        node.size.to_string(),
+                        node.files.to_string(),
+                        node.mtime.to_string(),
+                        node.ino.to_string()],
+                  separator)?;
+        write_nodes(out, &node.children, separator)?;
+    }
+
+    Ok(())
+}
+
+// Escaped path never contains tab or line breaks, so it is written to TSV as it is
+fn write_row<W: Write>(out: &mut W,
+                       path: &str,
+                       fields: &Vec<String>,
+                       separator: Separator)
+                       -> io::Result<()> {
+    let (sep, path, fields) = match separator {
+        Separator::Comma => {
+            (",", quote_csv(path), fields.iter().map(|f| quote_csv(f)).collect::<Vec<_>>())
+        }
+        Separator::Tab => {
+            ("\t", path.to_string(), fields.iter().map(|f| escape_tsv(f)).collect::<Vec<_>>())
+        }
+    };
+
+    writeln!(out, "{}{}{}", path, sep, fields.join(sep))
+}
+
+pub fn quote_csv(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn escape_tsv(field: &str) -> String {
+    let mut res = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => res.push_str("\\\\"),
+            '\t' => res.push_str("\\t"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn dir(path: &str, size: u64, children: Vec<JsonNode>) -> JsonNode {
+        JsonNode {
+            kind: "dir",
+            own_size: 4,
+            files_size: size - 4,
+            children: children,
+            ..JsonNode::leaf(path, 0, size)
+        }
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote_csv("plain name"), "plain name");
+        assert_eq!(quote_csv("a,b"), "\"a,b\"");
+        assert_eq!(quote_csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(quote_csv("line\nbreak"), "\"line\nbreak\"");
+        assert_eq!(escape_tsv("a\tb\\c\nd"), "a\\tb\\\\c\\nd");
+    }
+
+    #[test]
+    fn rows() {
+        let file = JsonNode {
+            mtime: 7,
+            ino: 3,
+            ..JsonNode::leaf("/r/a,\"b\"", 1, 10)
+        };
+        let roots = vec![dir("/r", 14, vec![file])];
+
+        let mut out = Vec::new();
+        write(&mut out, &roots, Separator::Comma).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "path,type,depth,own_size,files_size,dirs_size,total_size,files,mtime,ino\n\
+                    /r,dir,0,4,10,0,14,1,0,0\n\
+                    \"/r/a,\"\"b\"\"\",file,1,10,0,0,10,1,7,3\n");
+
+        let mut out = Vec::new();
+        write(&mut out, &roots, Separator::Tab).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().nth(2),
+                   Some("/r/a,\"b\"\tfile\t1\t10\t0\t0\t10\t1\t7\t3"));
+    }
+
+    #[test]
+    fn escaped_paths() {
+        let file = JsonNode {
+            os_path: PathBuf::from(OsStr::from_bytes(b"/r/latin\xe9\tx")),
+            ..JsonNode::leaf("/r/latin", 1, 10)
+        };
+        let roots = vec![dir("/r", 14, vec![file])];
+
+        let mut out = Vec::new();
+        write(&mut out, &roots, Separator::Comma).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("\n/r/latin\\xe9\\x09x,file,1,"));
+
+        let mut out = Vec::new();
+        write(&mut out, &roots, Separator::Tab).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("\n/r/latin\\xe9\\x09x\tfile\t1\t"));
+    }
+}
@@ -0,0 +1,281 @@
+//! Size changes between two trees, usually loaded from snapshots of the same roots.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use types::event_type::EventType;
+use types::formatter::{escape_os_str, human_format_if_needed};
+use types::path_cache::{descend, PathCacheInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffEntry {
+    pub path: PathBuf,
+    pub kind: DiffKind,
+    pub is_dir: bool,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+impl DiffEntry {
+    pub fn delta(&self) -> i64 {
+        self.new_size as i64 - self.old_size as i64
+    }
+}
+
+/// Compares trees with already calculated sizes. Added and removed directories are reported
+/// together with their contents down to `max_depth`, existing items are reported when their total
+/// size changed. Entries whose absolute change is below `threshold` are skipped, the rest is
+/// sorted by absolute change.
+pub fn diff(old: &BTreeMap<OsString, PathCacheInfo>,
+            new: &BTreeMap<OsString, PathCacheInfo>,
+            max_depth: u16,
+            threshold: u64)
+            -> Vec<DiffEntry> {
+    let mut res = Vec::new();
+    diff_level(old, new, &mut Vec::new(), 0, max_depth, &mut res);
+
+    res.retain(|e| {
+        e.delta().abs() as u64 >= threshold && (e.kind != DiffKind::Changed || e.delta() != 0)
+    });
+    res.sort_by(|a, b| {
+        b.delta()
+            .abs()
+            .cmp(&a.delta().abs())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    res
+}
+
+// Both levels are sorted by name, so they are merged in a single pass
+fn diff_level(old: &BTreeMap<OsString, PathCacheInfo>,
+              new: &BTreeMap<OsString, PathCacheInfo>,
+              prefix: &mut Vec<u8>,
+              depth: u16,
+              max_depth: u16,
+              res: &mut Vec<DiffEntry>) {
+    let mut old_iter = old.iter().peekable();
+    let mut new_iter = new.iter().peekable();
+
+    loop {
+        let order = match (old_iter.peek(), new_iter.peek()) {
+            (Some(&(o, _)), Some(&(n, _))) => o.cmp(n),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => break,
+        };
+
+        match order {
+            Ordering::Less => {
+                let (_, node) = old_iter.next().unwrap();
+                diff_subtree(node, DiffKind::Removed, prefix, depth, max_depth, res);
+            }
+            Ordering::Greater => {
+                let (_, node) = new_iter.next().unwrap();
+                diff_subtree(node, DiffKind::Added, prefix, depth, max_depth, res);
+            }
+            Ordering::Equal => {
+                let (_, old_node) = old_iter.next().unwrap();
+                let (_, new_node) = new_iter.next().unwrap();
+                diff_node(old_node, new_node, prefix, depth, max_depth, res);
+            }
+        }
+    }
+}
+
+fn diff_node(old: &PathCacheInfo,
+             new: &PathCacheInfo,
+             prefix: &mut Vec<u8>,
+             depth: u16,
+             max_depth: u16,
+             res: &mut Vec<DiffEntry>) {
+    // Path prefixes above the scanned roots carry no data and are not reported
+    if new.data.is_some() {
+        res.push(entry(prefix,
+                       depth,
+                       new,
+                       DiffKind::Changed,
+                       old.total_size(),
+                       new.total_size()));
+    }
+
+    if !descend(depth, max_depth) {
+        return;
+    }
+
+    let len = prefix.len();
+    push_name(prefix, depth, new);
+
+    let empty = BTreeMap::new();
+    diff_level(old.childs.as_ref().unwrap_or(&empty),
+               new.childs.as_ref().unwrap_or(&empty),
+               prefix,
+               depth + 1,
+               max_depth,
+               res);
+
+    prefix.truncate(len);
+}
+
+// Subtree present on one side only, every item in it is added or removed as a whole
+fn diff_subtree(node: &PathCacheInfo,
+                kind: DiffKind,
+                prefix: &mut Vec<u8>,
+                depth: u16,
+                max_depth: u16,
+                res: &mut Vec<DiffEntry>) {
+    if node.data.is_some() {
+        let (old_size, new_size) = match kind {
+            DiffKind::Removed => (node.total_size(), 0),
+            _ => (0, node.total_size()),
+        };
+        res.push(entry(prefix, depth, node, kind, old_size, new_size));
+    }
+
+    let childs = match node.childs {
+        Some(ref childs) if descend(depth, max_depth) => childs,
+        _ => return,
+    };
+
+    let len = prefix.len();
+    push_name(prefix, depth, node);
+    for child in childs.values() {
+        diff_subtree(child, kind, prefix, depth + 1, max_depth, res);
+    }
+    prefix.truncate(len);
+}
+
+fn entry(prefix: &Vec<u8>,
+         depth: u16,
+         node: &PathCacheInfo,
+         kind: DiffKind,
+         old_size: u64,
+         new_size: u64)
+         -> DiffEntry {
+    let mut path = prefix.clone();
+    push_name(&mut path, depth, node);
+
+    DiffEntry {
+        path: PathBuf::from(OsStr::from_bytes(&path)),
+        kind: kind,
+        is_dir: is_dir(node),
+        old_size: old_size,
+        new_size: new_size,
+    }
+}
+
+fn push_name(path: &mut Vec<u8>, depth: u16, node: &PathCacheInfo) {
+    if depth > 0 {
+        path.push(b'/');
+    }
+    path.extend_from_slice(node.path.as_bytes());
+}
+
+fn is_dir(node: &PathCacheInfo) -> bool {
+    match node.data {
+        Some(ref info) => {
+            match info.event_type {
+                EventType::File | EventType::Excluded => false,
+                _ => true,
+            }
+        }
+        None => true,
+    }
+}
+
+pub fn print(entries: &Vec<DiffEntry>, human_readable: bool) {
+    for e in entries.iter() {
+        let sign = if e.delta() < 0 { "-" } else { "+" };
+        let delta = human_format_if_needed(e.delta().abs() as u64, human_readable);
+        let suffix = if e.is_dir { "/" } else { "" };
+
+        let details = match e.kind {
+            DiffKind::Added => String::from("new"),
+            DiffKind::Removed => String::from("deleted"),
+            DiffKind::Changed => {
+                format!("{} -> {}",
+                        human_format_if_needed(e.old_size, human_readable),
+                        human_format_if_needed(e.new_size, human_readable))
+            }
+        };
+
+        println!("{:>12} {}{} ({})",
+                 format!("{}{}", sign, delta),
+                 escape_os_str(e.path.as_os_str()),
+                 suffix,
+                 details);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use types::path_cache::fixture;
+
+    // Paths ending with `/` are directories
+    fn tree(items: &[(&str, u64)]) -> BTreeMap<OsString, PathCacheInfo> {
+        let items = items.iter()
+            .map(|&(path, size)| if path.ends_with('/') {
+                (path.trim_right_matches('/'), EventType::DirEnter, size)
+            } else {
+                (path, EventType::File, size)
+            })
+            .collect::<Vec<_>>();
+        fixture::tree(&items)
+    }
+
+    fn summary(entries: &Vec<DiffEntry>) -> Vec<(PathBuf, DiffKind, i64)> {
+        entries.iter().map(|e| (e.path.clone(), e.kind, e.delta())).collect()
+    }
+
+    #[test]
+    fn grown_shrunk_added_removed() {
+        let old = tree(&[("/r/", 0), ("/r/a/", 0), ("/r/a/x", 100), ("/r/b/", 0),
+                         ("/r/b/y", 50), ("/r/gone/", 0), ("/r/gone/z", 30)]);
+        let new = tree(&[("/r/", 0), ("/r/a/", 0), ("/r/a/x", 1000), ("/r/b/", 0),
+                         ("/r/b/y", 40), ("/r/new", 7), ("/r/added/", 0), ("/r/added/w", 25)]);
+
+        let entries = diff(&old, &new, 0, 0);
+        assert_eq!(summary(&entries),
+                   vec![(PathBuf::from("/r/a"), DiffKind::Changed, 900),
+                        (PathBuf::from("/r/a/x"), DiffKind::Changed, 900),
+                        (PathBuf::from("/r"), DiffKind::Changed, 892),
+                        (PathBuf::from("/r/gone"), DiffKind::Removed, -30),
+                        (PathBuf::from("/r/gone/z"), DiffKind::Removed, -30),
+                        (PathBuf::from("/r/added"), DiffKind::Added, 25),
+                        (PathBuf::from("/r/added/w"), DiffKind::Added, 25),
+                        (PathBuf::from("/r/b"), DiffKind::Changed, -10),
+                        (PathBuf::from("/r/b/y"), DiffKind::Changed, -10),
+                        (PathBuf::from("/r/new"), DiffKind::Added, 7)]);
+        assert!(entries[0].is_dir && !entries[1].is_dir);
+
+        let entries = diff(&old, &new, 2, 20);
+        assert_eq!(summary(&entries),
+                   vec![(PathBuf::from("/r/a"), DiffKind::Changed, 900),
+                        (PathBuf::from("/r"), DiffKind::Changed, 892),
+                        (PathBuf::from("/r/gone"), DiffKind::Removed, -30),
+                        (PathBuf::from("/r/added"), DiffKind::Added, 25)]);
+    }
+
+    #[test]
+    fn empty_added_and_removed() {
+        let old = tree(&[("/r/", 0), ("/r/same", 5), ("/r/gone", 0)]);
+        let new = tree(&[("/r/", 0), ("/r/same", 5), ("/r/new", 0)]);
+
+        assert_eq!(summary(&diff(&old, &new, 0, 0)),
+                   vec![(PathBuf::from("/r/gone"), DiffKind::Removed, 0),
+                        (PathBuf::from("/r/new"), DiffKind::Added, 0)]);
+        assert!(diff(&old, &new, 0, 1).is_empty());
+    }
+}
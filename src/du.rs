@@ -0,0 +1,310 @@
+//! Output formatted like GNU `du`, one `SIZE<tab>PATH` line per directory, children before
+//! their parents.
+//!
+//! Sizes are summed again while printing, so that hard linked files are counted and listed
+//! only once over all roots, like `du` does.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use types::event_type::EventType;
+use types::item_info::ItemSize;
+use types::options::OptionsDu;
+use types::path_cache::{path_parts, PathCache, PathCacheInfo};
+use types::size_mode::SizeMode;
+
+const UNITS: &'static [&'static str] = &["K", "M", "G", "T", "P", "E", "Z", "Y"];
+
+/// Writes lines of all `roots` in their order, roots missing in the tree are skipped
+pub fn write<W: Write>(out: &mut W,
+                       pc: &BTreeMap<OsString, PathCacheInfo>,
+                       roots: &Vec<PathBuf>,
+                       size_mode: SizeMode,
+                       opts: &OptionsDu)
+                       -> io::Result<()> {
+    let mut printer = Printer {
+        out: out,
+        size_mode: size_mode,
+        opts: opts,
+        seen: HashSet::new(),
+    };
+
+    let mut total = 0;
+    for root in roots.iter() {
+        if let Some(node) = PathCache::find(pc, &mut path_parts(root)) {
+            total += printer.node(node, root.as_os_str().as_bytes(), 0)?;
+        }
+    }
+
+    if opts.total {
+        printer.line(total, b"total")?;
+    }
+
+    Ok(())
+}
+
+struct Printer<'a, W: 'a + Write> {
+    out: &'a mut W,
+    size_mode: SizeMode,
+    opts: &'a OptionsDu,
+    seen: HashSet<(u64, u64)>,
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    // Returns size of the subtree in bytes
+    fn node(&mut self, node: &PathCacheInfo, path: &[u8], depth: u16) -> io::Result<u64> {
+        let data = match node.data {
+            Some(data) => data,
+            None => return Ok(0),
+        };
+
+        let is_dir = match data.event_type {
+            EventType::DirEnter => true,
+            EventType::File => false,
+            // Excluded items and other file systems are not visited by du at all
+            _ => return Ok(0),
+        };
+
+        if let Some(id) = data.link_id() {
+            if !self.seen.insert(id) {
+                return Ok(0);
+            }
+        }
+
+        let mut size = self.size_mode.size(&data);
+        if let Some(ref childs) = node.childs {
+            for child in childs.values() {
+                let mut child_path = path.to_vec();
+                if !child_path.ends_with(b"/") {
+                    child_path.push(b'/');
+                }
+                child_path.extend_from_slice(child.path.as_bytes());
+
+                size += self.node(child, &child_path, depth + 1)?;
+            }
+        }
+
+        let shown = depth == 0 || (self.opts.max_depth.map_or(true, |max| depth <= max) &&
+                                   (self.opts.all || is_dir));
+        if shown {
+            self.line(size, path)?;
+        }
+
+        Ok(size)
+    }
+
+    fn line(&mut self, size: u64, path: &[u8]) -> io::Result<()> {
+        let size = if self.opts.human {
+            human_size(size)
+        } else {
+            ((size + self.opts.block_size - 1) / self.opts.block_size).to_string()
+        };
+
+        self.out.write_all(size.as_bytes())?;
+        self.out.write_all(b"\t")?;
+        self.out.write_all(path)?;
+        self.out.write_all(b"\n")
+    }
+}
+
+/// Formats size like `du -h`, rounding up to one decimal place below 10 and to whole units above
+pub fn human_size(size: u64) -> String {
+    if size < 1024 {
+        return size.to_string();
+    }
+
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if value < 10.0 {
+        let tenths = (value * 10.0).ceil() / 10.0;
+        if tenths < 10.0 {
+            return format!("{:.1}{}", tenths, UNITS[unit]);
+        }
+    }
+
+    let value = value.ceil();
+    if value >= 1024.0 && unit + 1 < UNITS.len() {
+        return format!("1.0{}", UNITS[unit + 1]);
+    }
+    format!("{}{}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+    use std::process::Command;
+    use std::sync::mpsc;
+
+    use super::*;
+    use types::item_info::FsItemInfo;
+    use types::message_type::MessageType;
+    use types::options::OptionsScan;
+
+    // du lists whichever hard link it finds first in readdir order, so links are optional
+    fn fixture(links: bool) -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("empty")).unwrap();
+        File::create(root.join("small")).unwrap().write_all(b"hello").unwrap();
+        File::create(root.join("a/big")).unwrap().write_all(&vec![7; 100000]).unwrap();
+        File::create(root.join("a/b/mid")).unwrap().write_all(&vec![7; 5000]).unwrap();
+        File::create(root.join("a/b/zero")).unwrap();
+        if links {
+            fs::hard_link(root.join("a/big"), root.join("link")).unwrap();
+        }
+        symlink("a/big", root.join("a/sym")).unwrap();
+        tmp
+    }
+
+    fn scan(root: &Path) -> BTreeMap<OsString, PathCacheInfo> {
+        let (tx, rx) = mpsc::channel();
+        let opts = OptionsScan { special_files: true, ..OptionsScan::default() };
+        let _ = ::visit_dir(&tx, root, &opts);
+        drop(tx);
+
+        let mut tree = BTreeMap::new();
+        for (msg, path, info) in rx.iter() {
+            if let (MessageType::FsItem, Some(path), Some(info)) = (msg, path, info) {
+                let info: FsItemInfo = *info;
+                if let EventType::DirLeave = info.event_type {
+                    continue;
+                }
+                PathCache::construct(&mut tree, &mut path_parts(&path), &info);
+            }
+        }
+        tree
+    }
+
+    fn opts() -> OptionsDu {
+        OptionsDu {
+            enabled: true,
+            all: false,
+            total: false,
+            max_depth: None,
+            block_size: 1024,
+            human: false,
+        }
+    }
+
+    fn wims_du(root: &Path, size_mode: SizeMode, opts: &OptionsDu) -> Vec<String> {
+        let mut out = Vec::new();
+        write(&mut out, &scan(root), &vec![root.to_path_buf()], size_mode, opts).unwrap();
+        lines(out)
+    }
+
+    // du lists directories in the order of readdir, so lines are compared sorted
+    fn lines(out: Vec<u8>) -> Vec<String> {
+        let mut res = String::from_utf8(out).unwrap().lines().map(String::from).collect::<Vec<_>>();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn apparent_sizes() {
+        let tmp = fixture(true);
+        let root = tmp.path();
+        let dir_size = |path: &str| fs::symlink_metadata(root.join(path)).unwrap().len();
+        let sym = fs::symlink_metadata(root.join("a/sym")).unwrap().len();
+
+        let b = dir_size("a/b") + 5000;
+        let a = dir_size("a") + 100000 + sym + b;
+        let total = dir_size("") + 5 + a + dir_size("empty");
+
+        let opts = OptionsDu {
+            all: true,
+            total: true,
+            block_size: 1,
+            ..opts()
+        };
+        let path = |name: &str| root.join(name).to_str().unwrap().trim_right_matches('/').to_string();
+        let mut expected = vec![format!("{}\t{}", total, path("")),
+                                format!("{}\t{}", a, path("a")),
+                                format!("{}\t{}", b, path("a/b")),
+                                format!("100000\t{}", path("a/big")),
+                                format!("5000\t{}", path("a/b/mid")),
+                                format!("0\t{}", path("a/b/zero")),
+                                format!("{}\t{}", sym, path("a/sym")),
+                                format!("{}\t{}", dir_size("empty"), path("empty")),
+                                format!("5\t{}", path("small")),
+                                format!("{}\ttotal", total)];
+        expected.sort();
+
+        // Hard link `link` to `a/big` is counted once
+        assert_eq!(wims_du(root, SizeMode::Apparent, &opts), expected);
+    }
+
+    #[test]
+    fn human_sizes() {
+        assert_eq!(human_size(0), "0");
+        assert_eq!(human_size(1023), "1023");
+        assert_eq!(human_size(1024), "1.0K");
+        assert_eq!(human_size(4096), "4.0K");
+        assert_eq!(human_size(10 * 1024 - 1), "10K");
+        assert_eq!(human_size(1025), "1.1K");
+        assert_eq!(human_size(100000), "98K");
+        assert_eq!(human_size(1024 * 1024 - 1), "1.0M");
+        assert_eq!(human_size(5 * 1024 * 1024 * 1024), "5.0G");
+    }
+
+    #[test]
+    fn matches_gnu_du() {
+        let tmp = fixture(false);
+        let root = tmp.path();
+
+        let cases: Vec<(&[&str], SizeMode, OptionsDu)> =
+            vec![(&[], SizeMode::DiskUsage, opts()),
+                 (&["-a"], SizeMode::DiskUsage, OptionsDu { all: true, ..opts() }),
+                 (&["-s", "-c"],
+                  SizeMode::DiskUsage,
+                  OptionsDu {
+                      max_depth: Some(0),
+                      total: true,
+                      ..opts()
+                  }),
+                 (&["-a", "--max-depth=1"],
+                  SizeMode::DiskUsage,
+                  OptionsDu {
+                      all: true,
+                      max_depth: Some(1),
+                      ..opts()
+                  }),
+                 (&["-a", "-h"], SizeMode::DiskUsage, OptionsDu { all: true, human: true, ..opts() }),
+                 (&["-a", "--apparent-size"], SizeMode::Apparent, OptionsDu { all: true, ..opts() }),
+                 (&["-a", "-b"],
+                  SizeMode::Apparent,
+                  OptionsDu {
+                      all: true,
+                      block_size: 1,
+                      ..opts()
+                  })];
+
+        for (args, size_mode, opts) in cases {
+            let output = Command::new("du")
+                .args(args)
+                .arg(root)
+                .output()
+                .expect("GNU du has to be installed to compare with it");
+            assert!(output.status.success(),
+                    "du {:?} failed, GNU du is required: {}",
+                    args,
+                    String::from_utf8_lossy(&output.stderr));
+            let du = lines(output.stdout);
+
+            assert_eq!(wims_du(root, size_mode, &opts), du, "du {:?}", args);
+        }
+    }
+}
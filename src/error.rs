@@ -0,0 +1,82 @@
+use libc;
+use rusqlite;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::result;
+
+use types::formatter::escape_os_str;
+
+#[derive(Debug)]
+pub enum Error {
+    /// I/O error while accessing path
+    Io(PathBuf, io::Error),
+    /// Snapshot file which can not be loaded
+    InvalidSnapshot(PathBuf, &'static str),
+    /// History database error
+    Db(PathBuf, rusqlite::Error),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: &Path, err: io::Error) -> Error {
+        Error::Io(path.to_path_buf(), err)
+    }
+
+    pub fn db(path: &Path, err: rusqlite::Error) -> Error {
+        Error::Db(path.to_path_buf(), err)
+    }
+
+    pub fn path(&self) -> &Path {
+        match *self {
+            Error::Io(ref path, _) => path,
+            Error::InvalidSnapshot(ref path, _) => path,
+            Error::Db(ref path, _) => path,
+        }
+    }
+
+    pub fn errno(&self) -> i32 {
+        match *self {
+            Error::Io(_, ref err) => err.raw_os_error().unwrap_or(libc::EIO),
+            Error::InvalidSnapshot(..) => libc::EINVAL,
+            Error::Db(..) => libc::EIO,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref path, ref err) => {
+                write!(f, "{}: {}", escape_os_str(path.as_os_str()), err)
+            }
+            Error::InvalidSnapshot(ref path, reason) => {
+                write!(f, "{}: {}", escape_os_str(path.as_os_str()), reason)
+            }
+            Error::Db(ref path, ref err) => {
+                write!(f, "{}: {}", escape_os_str(path.as_os_str()), err)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_, ref err) => error::Error::description(err),
+            Error::InvalidSnapshot(_, reason) => reason,
+            Error::Db(..) => "history database error",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(_, ref err) => Some(err),
+            Error::Db(_, ref err) => Some(err),
+            Error::InvalidSnapshot(..) => None,
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Folded stacks for flamegraph tools, one `root;dir;file SIZE` line per file.
+//!
+//! Every line carries only the size not covered by other lines, so that the sums of all lines
+//! under a prefix give the size of that directory. Directories have a line with their own
+//! size, directories at the depth limit a line with the size of their whole subtree. With
+//! `--only-dirs` sizes of files are added to their directory instead. A line can not be shared
+//! between directories, so unless hard links are counted naively only the first link of an inode
+//! carries its size. Flamegraph tools have no escaping, so `;` and line breaks in names are
+//! replaced by `_`.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::io::{self, Write};
+
+use types::event_type::EventType;
+use types::hard_links::HardLinks;
+use types::item_info::{FsItemInfo, ItemSize};
+use types::options::OptionsTree;
+use types::path_cache::{descend, PathCacheInfo};
+
+// Hard links already written
+struct Links {
+    hard_links: HardLinks,
+    seen: HashSet<(u64, u64)>,
+}
+
+impl Links {
+    fn counted(&mut self, data: &FsItemInfo) -> bool {
+        match (self.hard_links, data.link_id()) {
+            (HardLinks::Naive, _) | (_, None) => true,
+            (_, Some(id)) => self.seen.insert(id),
+        }
+    }
+}
+
+pub fn write<W: Write>(out: &mut W,
+                       pc: &BTreeMap<OsString, PathCacheInfo>,
+                       hard_links: HardLinks,
+                       opts: &OptionsTree)
+                       -> io::Result<()> {
+    let mut links = Links {
+        hard_links: hard_links,
+        seen: HashSet::new(),
+    };
+    roots(out, pc, "", &mut links, opts)
+}
+
+// Path prefixes above the scanned roots become part of the root frame
+fn roots<W: Write>(out: &mut W,
+                   pc: &BTreeMap<OsString, PathCacheInfo>,
+                   prefix: &str,
+                   links: &mut Links,
+                   opts: &OptionsTree)
+                   -> io::Result<()> {
+    for v in pc.values() {
+        let name = frame(&v.path);
+        let path = if prefix.is_empty() && name.is_empty() {
+            String::from("/")
+        } else if prefix.is_empty() || prefix.ends_with('/') {
+            format!("{}{}", prefix, name)
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        match v.data {
+            Some(_) => node(out, v, &path, 0, links, opts)?,
+            None => {
+                if let Some(ref childs) = v.childs {
+                    roots(out, childs, &path, links, opts)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn node<W: Write>(out: &mut W,
+                  v: &PathCacheInfo,
+                  stack: &str,
+                  depth: u16,
+                  links: &mut Links,
+                  opts: &OptionsTree)
+                  -> io::Result<()> {
+    let data = match v.data {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+
+    let weight = match data.event_type {
+        EventType::DirEnter => {
+            if !descend(depth + 1, opts.max_depth) {
+                subtree_size(v, links)
+            } else {
+                let mut files_size = 0;
+                if let Some(ref childs) = v.childs {
+                    for child in childs.values() {
+                        if opts.only_dirs && child_is_file(child) {
+                            files_size += subtree_size(child, links);
+                            continue;
+                        }
+                        node(out,
+                             child,
+                             &format!("{};{}", stack, frame(&child.path)),
+                             depth + 1,
+                             links,
+                             opts)?;
+                    }
+                }
+
+                own_size(v) + files_size
+            }
+        }
+        EventType::DirLeave => return Ok(()),
+        _ => subtree_size(v, links),
+    };
+
+    if weight > 0 {
+        writeln!(out, "{} {}", stack, weight)?;
+    }
+
+    Ok(())
+}
+
+// Size of the item itself, without its childs
+fn own_size(v: &PathCacheInfo) -> u64 {
+    v.total_size() - v.files_size() - v.dirs_size()
+}
+
+// Sum of all lines the subtree would be written as
+fn subtree_size(v: &PathCacheInfo, links: &mut Links) -> u64 {
+    let data = match v.data {
+        Some(data) => data,
+        None => return 0,
+    };
+
+    match data.event_type {
+        EventType::DirEnter => {
+            let mut size = own_size(v);
+            if let Some(ref childs) = v.childs {
+                for child in childs.values() {
+                    size += subtree_size(child, links);
+                }
+            }
+            size
+        }
+        EventType::DirLeave => 0,
+        _ => if links.counted(&data) { v.total_size() } else { 0 },
+    }
+}
+
+fn child_is_file(v: &PathCacheInfo) -> bool {
+    match v.data {
+        Some(FsItemInfo { event_type: EventType::File, .. }) => true,
+        _ => false,
+    }
+}
+
+fn frame(name: &OsString) -> String {
+    name.to_string_lossy().replace(|c| c == ';' || c == '\n' || c == '\r', "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::graph_style::GraphStyle;
+    use types::path_cache::{fixture, PathCache};
+    use types::size_mode::SizeMode;
+    use types::tree_style::TreeStyle;
+
+    fn folded(pc: &BTreeMap<OsString, PathCacheInfo>,
+              hard_links: HardLinks,
+              max_depth: u16,
+              only_dirs: bool)
+              -> Vec<String> {
+        let opts = OptionsTree {
+            enabled: false,
+            max_depth: max_depth,
+            only_dirs: only_dirs,
+            graph_width: 0,
+            graph_style: GraphStyle::Ascii,
+            style: TreeStyle::Indent,
+            color: false,
+        };
+
+        let mut out = Vec::new();
+        write(&mut out, pc, hard_links, &opts).unwrap();
+        String::from_utf8(out).unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn stacks() {
+        let pc = fixture::tree(&[("/r/s", EventType::DirEnter, 4),
+                                 ("/r/s/a", EventType::DirEnter, 2),
+                                 ("/r/s/a/x;y", EventType::File, 100),
+                                 ("/r/s/a/b", EventType::DirEnter, 2),
+                                 ("/r/s/a/b/z", EventType::File, 20),
+                                 ("/r/s/w", EventType::File, 3)]);
+
+        assert_eq!(folded(&pc, HardLinks::Global, 0, false),
+                   vec!["/r/s;a;b;z 20", "/r/s;a;b 2", "/r/s;a;x_y 100", "/r/s;a 2", "/r/s;w 3", "/r/s 4"]);
+        assert_eq!(folded(&pc, HardLinks::Global, 2, false),
+                   vec!["/r/s;a 124", "/r/s;w 3", "/r/s 4"]);
+        assert_eq!(folded(&pc, HardLinks::Global, 0, true),
+                   vec!["/r/s;a;b 22", "/r/s;a 102", "/r/s 7"]);
+    }
+
+    #[test]
+    fn hard_links_weighed_once() {
+        let mut pc = BTreeMap::new();
+        fixture::insert(&mut pc, "/r", fixture::item(EventType::DirEnter, 1, 1, 4));
+        fixture::insert(&mut pc, "/r/a", fixture::item(EventType::DirEnter, 2, 1, 2));
+        fixture::insert(&mut pc, "/r/a/x", fixture::item(EventType::File, 10, 2, 100));
+        fixture::insert(&mut pc, "/r/b", fixture::item(EventType::DirEnter, 3, 1, 2));
+        fixture::insert(&mut pc, "/r/b/y", fixture::item(EventType::File, 10, 2, 100));
+
+        PathCache::calculate_sizes(&mut pc, HardLinks::Global, SizeMode::Apparent);
+        assert_eq!(folded(&pc, HardLinks::Global, 0, false),
+                   vec!["/r;a;x 100", "/r;a 2", "/r;b 2", "/r 4"]);
+        assert_eq!(folded(&pc, HardLinks::Global, 2, false),
+                   vec!["/r;a 102", "/r;b 2", "/r 4"]);
+        assert_eq!(folded(&pc, HardLinks::Global, 0, true),
+                   vec!["/r;a 102", "/r;b 2", "/r 4"]);
+
+        PathCache::calculate_sizes(&mut pc, HardLinks::Naive, SizeMode::Apparent);
+        assert_eq!(folded(&pc, HardLinks::Naive, 0, false),
+                   vec!["/r;a;x 100", "/r;a 2", "/r;b;y 100", "/r;b 2", "/r 4"]);
+    }
+}
@@ -0,0 +1,300 @@
+//! Scan history kept in SQLite database.
+//!
+//! Every recorded scan is a row in `runs`, its tree nodes are rows in `nodes` linked to their
+//! parent node. Paths are stored as raw bytes, so that they need not be valid UTF-8.
+
+use rusqlite::{Connection, Transaction, NO_PARAMS};
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use super::{Error, Result};
+use snapshot::Snapshot;
+use types::event_type::EventType;
+use types::path_cache::PathCacheInfo;
+
+const SCHEMA: &'static str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        start_time INTEGER NOT NULL,
+        host BLOB NOT NULL,
+        dirs INTEGER NOT NULL,
+        files INTEGER NOT NULL,
+        errors INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS roots (
+        run_id INTEGER NOT NULL REFERENCES runs(id),
+        path BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS nodes (
+        id INTEGER PRIMARY KEY,
+        run_id INTEGER NOT NULL REFERENCES runs(id),
+        parent_id INTEGER REFERENCES nodes(id),
+        path BLOB NOT NULL,
+        event_type TEXT NOT NULL,
+        mtime INTEGER NOT NULL,
+        total_size INTEGER NOT NULL,
+        dirs_size INTEGER NOT NULL,
+        files_size INTEGER NOT NULL,
+        apparent_size INTEGER NOT NULL,
+        disk_size INTEGER NOT NULL,
+        dirs INTEGER NOT NULL,
+        files INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS nodes_path ON nodes (path, run_id);
+";
+
+/// Size of a path in one recorded run
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub run_id: i64,
+    pub start_time: i64,
+    pub total_size: u64,
+    pub dirs: u64,
+    pub files: u64,
+}
+
+pub struct History {
+    path: PathBuf,
+    conn: Connection,
+}
+
+impl History {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<History> {
+        let path = path.as_ref();
+
+        let conn = Connection::open(path).map_err(|e| Error::db(path, e))?;
+        conn.execute_batch(SCHEMA).map_err(|e| Error::db(path, e))?;
+
+        Ok(History {
+            path: path.to_path_buf(),
+            conn: conn,
+        })
+    }
+
+    /// Stores the snapshot as a new run, sizes of its tree have to be calculated already.
+    /// Returns id of the run.
+    pub fn record(&mut self, snapshot: &Snapshot) -> Result<i64> {
+        let path = self.path.clone();
+        let tx = self.conn.transaction().map_err(|e| Error::db(&path, e))?;
+
+        let run_id = History::insert_run(&tx, snapshot).map_err(|e| Error::db(&path, e))?;
+        tx.commit().map_err(|e| Error::db(&path, e))?;
+
+        Ok(run_id)
+    }
+
+    /// Sizes of `path` in all runs which contain it, oldest first
+    pub fn size_history<P: AsRef<Path>>(&self, path: P) -> Result<Vec<HistoryEntry>> {
+        let bytes = path.as_ref().as_os_str().as_bytes();
+
+        let mut stmt = self.conn
+            .prepare("SELECT runs.id, runs.start_time, nodes.total_size, nodes.dirs, nodes.files
+                      FROM nodes JOIN runs ON runs.id = nodes.run_id
+                      WHERE nodes.path = ?1
+                      ORDER BY runs.start_time, runs.id")
+            .map_err(|e| Error::db(&self.path, e))?;
+
+        let rows = stmt.query_map(params![bytes], |row| {
+                Ok(HistoryEntry {
+                    run_id: row.get(0)?,
+                    start_time: row.get(1)?,
+                    total_size: row.get::<_, i64>(2)? as u64,
+                    dirs: row.get::<_, i64>(3)? as u64,
+                    files: row.get::<_, i64>(4)? as u64,
+                })
+            })
+            .map_err(|e| Error::db(&self.path, e))?;
+
+        rows.collect::<::std::result::Result<Vec<_>, _>>().map_err(|e| Error::db(&self.path, e))
+    }
+
+    /// Number of recorded runs
+    pub fn runs(&self) -> Result<u64> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM runs", NO_PARAMS, |row| row.get::<_, i64>(0))
+            .map(|count| count as u64)
+            .map_err(|e| Error::db(&self.path, e))
+    }
+
+    fn insert_run(tx: &Transaction, snapshot: &Snapshot) -> ::rusqlite::Result<i64> {
+        tx.execute("INSERT INTO runs (start_time, host, dirs, files, errors)
+                    VALUES (?1, ?2, ?3, ?4, ?5)",
+                   params![snapshot.start_time,
+                           snapshot.host.as_bytes(),
+                           snapshot.overall.dirs as i64,
+                           snapshot.overall.files as i64,
+                           snapshot.overall.errors as i64])?;
+        let run_id = tx.last_insert_rowid();
+
+        for root in snapshot.roots.iter() {
+            tx.execute("INSERT INTO roots (run_id, path) VALUES (?1, ?2)",
+                       params![run_id, root.as_os_str().as_bytes()])?;
+        }
+
+        let mut inserter = NodeInserter {
+            tx: tx,
+            run_id: run_id,
+        };
+        inserter.insert_level(&snapshot.tree, None, &mut Vec::new(), 0)?;
+
+        Ok(run_id)
+    }
+}
+
+struct NodeInserter<'a, 'conn: 'a> {
+    tx: &'a Transaction<'conn>,
+    run_id: i64,
+}
+
+impl<'a, 'conn> NodeInserter<'a, 'conn> {
+    fn insert_level(&mut self,
+                    pc: &BTreeMap<OsString, PathCacheInfo>,
+                    parent_id: Option<i64>,
+                    prefix: &mut Vec<u8>,
+                    depth: usize)
+                    -> ::rusqlite::Result<()> {
+        for (k, v) in pc.iter() {
+            let len = prefix.len();
+            if depth > 0 {
+                prefix.push(b'/');
+            }
+            prefix.extend_from_slice(k.as_bytes());
+
+            self.insert_node(v, parent_id, prefix, depth)?;
+
+            prefix.truncate(len);
+        }
+
+        Ok(())
+    }
+
+    fn insert_node(&mut self,
+                   node: &PathCacheInfo,
+                   parent_id: Option<i64>,
+                   path: &mut Vec<u8>,
+                   depth: usize)
+                   -> ::rusqlite::Result<()> {
+        // Path prefixes above the scanned roots are not stored
+        let info = match node.data {
+            Some(info) => info,
+            None => {
+                return match node.childs {
+                    Some(ref childs) => self.insert_level(childs, parent_id, path, depth + 1),
+                    None => Ok(()),
+                };
+            }
+        };
+
+        let counts = match node.childs {
+            Some(ref childs) => count_level(childs),
+            None => (0, 0),
+        };
+
+        self.tx
+            .prepare_cached("INSERT INTO nodes (run_id, parent_id, path, event_type, mtime,
+                                                total_size, dirs_size, files_size, apparent_size,
+                                                disk_size, dirs, files)
+                             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)")?
+            .execute(params![self.run_id,
+                             parent_id,
+                             &path[..],
+                             info.event_type.name(),
+                             info.mtime,
+                             node.total_size() as i64,
+                             node.dirs_size() as i64,
+                             node.files_size() as i64,
+                             node.apparent_size() as i64,
+                             node.disk_size() as i64,
+                             counts.0 as i64,
+                             counts.1 as i64])?;
+        let id = self.tx.last_insert_rowid();
+
+        if let Some(ref childs) = node.childs {
+            self.insert_level(childs, Some(id), path, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Counts of directories and files in the level including their subtrees
+fn count_level(pc: &BTreeMap<OsString, PathCacheInfo>) -> (u64, u64) {
+    pc.values().fold((0, 0), |acc, node| {
+        let own = match node.data.map(|info| info.event_type) {
+            Some(EventType::DirEnter) | Some(EventType::DirLeave) => (1, 0),
+            Some(EventType::File) => (0, 1),
+            _ => (0, 0),
+        };
+        let childs = match node.childs {
+            Some(ref childs) => count_level(childs),
+            None => (0, 0),
+        };
+        (acc.0 + own.0 + childs.0, acc.1 + own.1 + childs.1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use std::collections::BTreeMap;
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+
+    use super::*;
+    use snapshot::VERSION;
+    use types::hard_links::HardLinks;
+    use types::overall_info::OverallInfo;
+    use types::path_cache::PathCache;
+    use types::path_cache::fixture::{insert, item};
+    use types::size_mode::SizeMode;
+
+    fn snapshot(start_time: i64, files: &[(&str, u64)]) -> Snapshot {
+        let mut tree = BTreeMap::new();
+        insert(&mut tree, "/r", item(EventType::DirEnter, 0, 1, 0));
+        insert(&mut tree, "/r/a", item(EventType::DirEnter, 1, 1, 0));
+        for (i, &(path, size)) in files.iter().enumerate() {
+            insert(&mut tree, path, item(EventType::File, 100 + i as u64, 1, size));
+        }
+        PathCache::calculate_sizes(&mut tree, HardLinks::Global, SizeMode::Apparent);
+
+        Snapshot {
+            version: VERSION,
+            roots: vec![PathBuf::from("/r")],
+            start_time: start_time,
+            host: OsString::from("host"),
+            options: Vec::new(),
+            overall: OverallInfo::default(),
+            tree: tree,
+        }
+    }
+
+    #[test]
+    fn record_and_query() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("history.db");
+
+        {
+            let mut history = History::open(&path).unwrap();
+            history.record(&snapshot(200, &[("/r/a/x", 10), ("/r/y", 5)])).unwrap();
+            history.record(&snapshot(100, &[("/r/a/x", 7)])).unwrap();
+        }
+
+        let history = History::open(&path).unwrap();
+        assert_eq!(history.runs().unwrap(), 2);
+
+        let sizes = history.size_history("/r")
+            .unwrap()
+            .iter()
+            .map(|e| (e.start_time, e.total_size, e.dirs, e.files))
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(100, 7, 1, 1), (200, 15, 1, 2)]);
+
+        assert_eq!(history.size_history("/r/a/x").unwrap().len(), 2);
+        assert!(history.size_history("/r/y").unwrap()[0].start_time == 200);
+        assert!(history.size_history("/nonexistent").unwrap().is_empty());
+    }
+}
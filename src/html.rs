@@ -0,0 +1,61 @@
+//! Self-contained HTML report. The tree is embedded as the `--format json` document into a page
+//! with a collapsible, sortable table and a treemap, which works offline in any browser.
+
+use std::io::{self, Write};
+
+use json::{self, JsonNode};
+use types::size_mode::SizeMode;
+
+const TEMPLATE: &'static str = include_str!("report.html");
+
+/// Placeholder in the template replaced by the JSON document
+const DATA_MARKER: &'static str = "/*WIMS_DATA*/null";
+
+pub fn write<W: Write>(out: &mut W, roots: &Vec<JsonNode>, size_mode: SizeMode) -> io::Result<()> {
+    let mut data = Vec::new();
+    json::write(&mut data, roots, size_mode)?;
+    let data = String::from_utf8_lossy(&data);
+
+    let pos = TEMPLATE.find(DATA_MARKER).expect("Report template without data marker");
+    out.write_all(TEMPLATE[..pos].as_bytes())?;
+    out.write_all(escape_script(data.trim_right()).as_bytes())?;
+    out.write_all(TEMPLATE[pos + DATA_MARKER.len()..].as_bytes())
+}
+
+/// Makes JSON safe to embed into `<script>`, file names such as `</script>` or `<!--` must not
+/// end the element early. `<` only occurs inside strings, where the escape keeps its meaning.
+pub fn escape_script(json: &str) -> String {
+    json.replace('<', "\\u003c")
+        .replace('\u{2028}', "\\u2028")
+        .replace('\u{2029}', "\\u2029")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+
+    use super::*;
+
+    #[test]
+    fn embedded_data() {
+        let roots = vec![JsonNode {
+                             kind: "dir",
+                             children: vec![JsonNode::leaf("/r/</script><!--", 1, 10)],
+                             ..JsonNode::leaf("/r", 0, 10)
+                         }];
+
+        let mut out = Vec::new();
+        write(&mut out, &roots, SizeMode::Apparent).unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert_eq!(html.matches("</script>").count(), 1);
+        assert!(!html.contains(DATA_MARKER));
+
+        let start = html.find("var data = ").unwrap() + "var data = ".len();
+        let end = start + html[start..].find(";\n").unwrap();
+        let data: Value = serde_json::from_str(&html[start..end]).unwrap();
+        assert_eq!(data["schema_version"], json::SCHEMA_VERSION);
+        assert_eq!(data["roots"][0]["children"][0]["path"], "/r/</script><!--");
+    }
+}
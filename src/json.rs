@@ -0,0 +1,412 @@
+//! JSON output of the aggregated tree.
+//!
+//! Schema version 1, `--format json` writes a single document
+//!
+//! ```text
+//! {"schema_version": 1, "size_mode": "apparent", "roots": [NODE, ...]}
+//! ```
+//!
+//! and `--format json-lines` writes every `NODE` without `children` on its own line, parents
+//! before their children, each line carrying its own `schema_version`. `NODE` is
+//!
+//! ```text
+//! {
+//!   "name": "x",               last path component, whole path for roots
+//!   "path": "/r/a/x",          full path
+//!   "depth": 2,                0 for scanned roots
+//!   "type": "file",            "dir", "file", "mount_point" or "excluded"
+//!   "mtime": 1500000000,       seconds since epoch
+//!   "ino": 12,
+//!   "own_size": 4096,          size of the node itself according to size_mode
+//!   "size": 4096,              total size according to size_mode
+//!   "files_size": 0,           size of files directly inside
+//!   "dirs_size": 0,            size of subdirectories
+//!   "apparent_size": 4000,     sizes regardless of size_mode
+//!   "disk_size": 4096,
+//!   "dirs": 0,                 directories and files in the subtree, not counting the node
+//!   "files": 0,
+//!   "children": [NODE, ...]
+//! }
+//! ```
+//!
+//! Names and paths are escaped like in the text output, bytes which are not valid UTF-8 and
+//! control characters are written as `\xNN` and backslash as `\\`, so that distinct paths
+//! stay distinct.
+//! New fields may be added without changing the version, removing or changing meaning of
+//! fields bumps it.
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde_json;
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use types::event_type::EventType;
+use types::formatter::escape_os_str;
+use types::item_info::ItemSize;
+use types::options::{OptionsSort, OptionsTree};
+use types::path_cache::{descend, PathCache, PathCacheInfo};
+use types::size_mode::SizeMode;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonNode {
+    pub name: String,
+    pub path: String,
+    /// Path as found on disk, `path` is escaped
+    pub os_path: PathBuf,
+    pub depth: u16,
+    pub kind: &'static str,
+    pub mtime: i64,
+    pub ino: u64,
+    pub own_size: u64,
+    pub size: u64,
+    pub files_size: u64,
+    pub dirs_size: u64,
+    pub apparent_size: u64,
+    pub disk_size: u64,
+    pub dirs: u64,
+    pub files: u64,
+    pub children: Vec<JsonNode>,
+}
+
+#[cfg(test)]
+impl JsonNode {
+    /// File of `size` bytes, other nodes are built from it by struct update
+    pub fn leaf(path: &str, depth: u16, size: u64) -> JsonNode {
+        JsonNode {
+            name: Path::new(path).file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            path: path.to_string(),
+            os_path: PathBuf::from(path),
+            depth: depth,
+            kind: "file",
+            mtime: 0,
+            ino: 0,
+            own_size: size,
+            size: size,
+            files_size: 0,
+            dirs_size: 0,
+            apparent_size: size,
+            disk_size: 0,
+            dirs: 0,
+            files: 1,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Converts a tree with calculated sizes, path prefixes above the scanned roots are skipped.
+/// Filters and order of `opts` and `sort` are applied the same way as for the printed tree.
+pub fn nodes(pc: &BTreeMap<OsString, PathCacheInfo>,
+             opts: &OptionsTree,
+             sort: &OptionsSort)
+             -> Vec<JsonNode> {
+    let mut res = Vec::new();
+    roots(pc, Path::new(""), opts, sort, &mut res);
+    res
+}
+
+fn roots(pc: &BTreeMap<OsString, PathCacheInfo>,
+         prefix: &Path,
+         opts: &OptionsTree,
+         sort: &OptionsSort,
+         res: &mut Vec<JsonNode>) {
+    for v in PathCache::sorted(pc, sort) {
+        let path = join(prefix, &v.path);
+        match v.data {
+            Some(_) => {
+                if let Some(node) = node(v, &path, 0, opts, sort) {
+                    res.push(node.0);
+                }
+            }
+            None => {
+                if let Some(ref childs) = v.childs {
+                    roots(childs, &path, opts, sort, res);
+                }
+            }
+        }
+    }
+}
+
+// Returns the node with counts of directories and files including the node itself. Counts
+// cover the whole subtree even when children are cut off by depth or filters.
+fn node(v: &PathCacheInfo,
+        path: &Path,
+        depth: u16,
+        opts: &OptionsTree,
+        sort: &OptionsSort)
+        -> Option<(JsonNode, u64, u64)> {
+    let data = v.data?;
+    let (kind, own) = match *data.event_type() {
+        EventType::DirEnter => ("dir", (1, 0)),
+        EventType::File => ("file", (0, 1)),
+        EventType::MountPoint => ("mount_point", (0, 0)),
+        EventType::Excluded => ("excluded", (0, 0)),
+        EventType::DirLeave => return None,
+    };
+
+    let mut children = Vec::new();
+    let (mut dirs, mut files) = (0, 0);
+    if let Some(ref childs) = v.childs {
+        let show = descend(depth + 1, opts.max_depth);
+        for child in PathCache::sorted(childs, sort) {
+            if let Some((child_node, child_dirs, child_files)) =
+                node(child, &join(path, &child.path), depth + 1, opts, sort) {
+                dirs += child_dirs;
+                files += child_files;
+
+                if show && !(opts.only_dirs && child_node.kind != "dir" &&
+                             child_node.kind != "mount_point") {
+                    children.push(child_node);
+                }
+            }
+        }
+    }
+
+    let name = if depth == 0 {
+        path.as_os_str()
+    } else {
+        v.path.as_os_str()
+    };
+
+    Some((JsonNode {
+              name: escape_os_str(name),
+              path: escape_os_str(path.as_os_str()),
+              os_path: path.to_path_buf(),
+              depth: depth,
+              kind: kind,
+              mtime: data.mtime(),
+              ino: data.ino,
+              own_size: v.total_size() - v.files_size() - v.dirs_size(),
+              size: v.total_size(),
+              files_size: v.files_size(),
+              dirs_size: v.dirs_size(),
+              apparent_size: v.apparent_size(),
+              disk_size: v.disk_size(),
+              dirs: dirs,
+              files: files,
+              children: children,
+          },
+          dirs + own.0,
+          files + own.1))
+}
+
+// Absolute paths start with an empty component, which has to become `/`
+fn join(prefix: &Path, name: &OsString) -> PathBuf {
+    if prefix.as_os_str().is_empty() && name.is_empty() {
+        PathBuf::from("/")
+    } else {
+        prefix.join(name)
+    }
+}
+
+fn size_mode_name(size_mode: SizeMode) -> &'static str {
+    match size_mode {
+        SizeMode::Apparent => "apparent",
+        SizeMode::DiskUsage => "disk_usage",
+    }
+}
+
+/// Writes the whole tree as one JSON document
+pub fn write<W: Write>(out: &mut W, roots: &Vec<JsonNode>, size_mode: SizeMode) -> io::Result<()> {
+    serde_json::to_writer(&mut *out,
+                          &Document {
+                              roots: roots,
+                              size_mode: size_mode,
+                          })
+        .map_err(io::Error::from)?;
+    writeln!(out)
+}
+
+/// Writes every node on its own line, parents first
+pub fn write_lines<W: Write>(out: &mut W, roots: &Vec<JsonNode>) -> io::Result<()> {
+    for node in roots.iter() {
+        serde_json::to_writer(&mut *out, &Line(node)).map_err(io::Error::from)?;
+        writeln!(out)?;
+        write_lines(out, &node.children)?;
+    }
+
+    Ok(())
+}
+
+struct Document<'a> {
+    roots: &'a Vec<JsonNode>,
+    size_mode: SizeMode,
+}
+
+impl<'a> Serialize for Document<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("Document", 3)?;
+        s.serialize_field("schema_version", &SCHEMA_VERSION)?;
+        s.serialize_field("size_mode", size_mode_name(self.size_mode))?;
+        s.serialize_field("roots", self.roots)?;
+        s.end()
+    }
+}
+
+impl Serialize for JsonNode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("JsonNode", 15)?;
+        serialize_fields::<S>(&mut s, self)?;
+        s.serialize_field("children", &self.children)?;
+        s.end()
+    }
+}
+
+// Node without children, used for JSON lines
+struct Line<'a>(&'a JsonNode);
+
+impl<'a> Serialize for Line<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("JsonNode", 15)?;
+        s.serialize_field("schema_version", &SCHEMA_VERSION)?;
+        serialize_fields::<S>(&mut s, self.0)?;
+        s.end()
+    }
+}
+
+fn serialize_fields<S>(s: &mut S::SerializeStruct, node: &JsonNode) -> Result<(), S::Error>
+    where S: Serializer
+{
+    s.serialize_field("name", &node.name)?;
+    s.serialize_field("path", &node.path)?;
+    s.serialize_field("depth", &node.depth)?;
+    s.serialize_field("type", node.kind)?;
+    s.serialize_field("mtime", &node.mtime)?;
+    s.serialize_field("ino", &node.ino)?;
+    s.serialize_field("own_size", &node.own_size)?;
+    s.serialize_field("size", &node.size)?;
+    s.serialize_field("files_size", &node.files_size)?;
+    s.serialize_field("dirs_size", &node.dirs_size)?;
+    s.serialize_field("apparent_size", &node.apparent_size)?;
+    s.serialize_field("disk_size", &node.disk_size)?;
+    s.serialize_field("dirs", &node.dirs)?;
+    s.serialize_field("files", &node.files)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{self, Value};
+
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    use super::*;
+    use types::graph_style::GraphStyle;
+    use types::hard_links::HardLinks;
+    use types::path_cache::{fixture, path_parts};
+    use types::sort_by::SortBy;
+    use types::tree_style::TreeStyle;
+
+    fn tree() -> BTreeMap<OsString, PathCacheInfo> {
+        fixture::tree(&[("/r", EventType::DirEnter, 0),
+                        ("/r/a", EventType::DirEnter, 0),
+                        ("/r/a/x", EventType::File, 100),
+                        ("/r/a/y", EventType::File, 20),
+                        ("/r/z", EventType::File, 3)])
+    }
+
+    fn opts(max_depth: u16, only_dirs: bool) -> OptionsTree {
+        OptionsTree {
+            enabled: false,
+            max_depth: max_depth,
+            only_dirs: only_dirs,
+            graph_width: 0,
+            graph_style: GraphStyle::Ascii,
+            style: TreeStyle::Indent,
+            color: false,
+        }
+    }
+
+    fn sort() -> OptionsSort {
+        OptionsSort {
+            by: SortBy::Name,
+            reverse: false,
+        }
+    }
+
+    #[test]
+    fn document() {
+        let roots = nodes(&tree(), &opts(0, false), &sort());
+        let mut out = Vec::new();
+        write(&mut out, &roots, SizeMode::Apparent).unwrap();
+
+        let doc: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(doc["schema_version"], 1);
+        assert_eq!(doc["size_mode"], "apparent");
+
+        let root = &doc["roots"][0];
+        assert_eq!(root["name"], "/r");
+        assert_eq!(root["path"], "/r");
+        assert_eq!(root["type"], "dir");
+        assert_eq!(root["size"], 123);
+        assert_eq!(root["own_size"], 0);
+        assert_eq!(root["ino"], 0);
+        assert_eq!(root["dirs"], 1);
+        assert_eq!(root["files"], 3);
+
+        let a = &root["children"][0];
+        assert_eq!(a["name"], "a");
+        assert_eq!(a["path"], "/r/a");
+        assert_eq!(a["depth"], 1);
+        assert_eq!(a["files_size"], 120);
+        assert_eq!(a["children"][1]["path"], "/r/a/y");
+        assert_eq!(a["children"][1]["mtime"], 3);
+        assert_eq!(root["children"][1]["type"], "file");
+    }
+
+    #[test]
+    fn lines_depth_and_only_dirs() {
+        let roots = nodes(&tree(), &opts(2, true), &sort());
+        let mut out = Vec::new();
+        write_lines(&mut out, &roots).unwrap();
+
+        let lines = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["schema_version"], 1);
+        assert_eq!(lines[0]["path"], "/r");
+        assert_eq!(lines[1]["path"], "/r/a");
+        assert_eq!(lines[1]["files"], 2);
+        assert!(lines[1].get("children").is_none());
+    }
+
+    #[test]
+    fn escaped_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut pc = BTreeMap::new();
+        fixture::insert(&mut pc, "/r", fixture::item(EventType::DirEnter, 1, 1, 0));
+        let names: [&[u8]; 3] = [b"/r/caf\xe9", b"/r/caf\xe8", b"/r/a\\b"];
+        for (i, name) in names.iter().enumerate() {
+            PathCache::construct(&mut pc,
+                                 &mut path_parts(Path::new(OsStr::from_bytes(name))),
+                                 &fixture::item(EventType::File, i as u64 + 2, 1, 1));
+        }
+        PathCache::calculate_sizes(&mut pc, HardLinks::Naive, SizeMode::Apparent);
+
+        let roots = nodes(&pc, &opts(0, false), &sort());
+        let children = roots[0]
+            .children
+            .iter()
+            .map(|node| (node.name.as_str(), node.path.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(children,
+                   vec![("a\\\\b", "/r/a\\\\b"),
+                        ("caf\\xe8", "/r/caf\\xe8"),
+                        ("caf\\xe9", "/r/caf\\xe9")]);
+    }
+}
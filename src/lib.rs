@@ -5,102 +5,468 @@ extern crate env_logger;
 #[macro_use]
 #[cfg(test)]
 extern crate quickcheck;
+#[cfg(test)]
+extern crate bincode;
 
+extern crate ansi_term;
 extern crate clap;
+extern crate image;
+extern crate imageproc;
+extern crate libc;
+extern crate regex;
+#[macro_use]
+extern crate rusqlite;
 extern crate serde;
+extern crate serde_json;
 
+use std::collections::HashMap;
 use std::fs::{self, DirEntry};
-use std::io;
-use std::path::Path;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 
+pub mod browse;
+pub mod csv;
+pub mod diff;
+pub mod du;
+pub mod error;
+pub mod folded;
+pub mod history;
+pub mod html;
+pub mod json;
+pub mod ncdu;
+pub mod snapshot;
+pub mod svg;
+pub mod treemap;
 pub mod types;
+pub mod walker;
+
+pub use error::{Error, Result};
 
 use types::dir_info::FsDirInfo;
 use types::event_type::EventType;
 use types::item_info::FsItemInfo;
 use types::message_type::MessageType;
+use types::filter::Filter;
+use types::options::{Options, OptionsScan};
+use types::path_cache::{path_parts, PathCache, PathCacheInfo, EXCLUDED_KEY};
 
-pub type FsStack = Vec<FsDirInfo>;
+/// Directories whose listing is still in progress, keyed by path.
+pub type FsDirs = HashMap<PathBuf, FsDirInfo>;
 
-pub type RxChannel = mpsc::Receiver<(MessageType, Option<String>, Option<Box<FsItemInfo>>)>;
-pub type TxChannel = mpsc::Sender<(MessageType, Option<String>, Option<Box<FsItemInfo>>)>;
+pub type RxChannel = mpsc::Receiver<(MessageType, Option<PathBuf>, Option<Box<FsItemInfo>>)>;
+pub type TxChannel = mpsc::Sender<(MessageType, Option<PathBuf>, Option<Box<FsItemInfo>>)>;
 
-pub fn get_file_info(event_type: &EventType, entry: &DirEntry) -> Box<FsItemInfo> {
-    let md = Box::new(entry.metadata().unwrap()) as Box<std::os::unix::fs::MetadataExt>;
+pub fn get_file_info(event_type: &EventType, entry: &DirEntry) -> Result<Box<FsItemInfo>> {
+    let md = entry.metadata().map_err(|e| Error::io(&entry.path(), e))?;
+    Ok(Box::new(self::metadata_info(event_type, &md)))
+}
 
-    Box::new(FsItemInfo {
+fn metadata_info(event_type: &EventType, md: &MetadataExt) -> FsItemInfo {
+    FsItemInfo {
         event_type: *event_type,
+        dev: md.dev(),
         ino: md.ino(),
+        nlink: md.nlink(),
         mtime: md.mtime(),
         size: md.size(),
-    })
+        blocks: md.blocks(),
+    }
 }
 
-pub fn process(tx: &TxChannel, dirs: &Vec<String>) {
-    for dir in dirs.iter() {
-        let _ = self::visit_dir(tx, Path::new(dir));
+pub fn process(tx: &TxChannel, dirs: &Vec<PathBuf>, opts: &Options) {
+    if opts.scan.threads > 1 {
+        walker::walk(tx, dirs, &opts.scan);
+    } else {
+        for dir in dirs.iter() {
+            let _ = self::visit_dir(tx, dir, &opts.scan);
+        }
     }
 }
 
-pub fn visit_dir(tx: &TxChannel, dir: &Path) -> io::Result<()> {
+/// Recursively visits `dir`, subdirectories are visited before `DirLeave` of their parent is sent.
+pub fn visit_dir(tx: &TxChannel, dir: &Path, opts: &OptionsScan) -> Result<()> {
+    self::visit_subdir(tx, dir, dir, opts)
+}
+
+fn visit_subdir(tx: &TxChannel, root: &Path, dir: &Path, opts: &OptionsScan) -> Result<()> {
+    self::scan_dir(tx, root, dir, opts, &mut |path| {
+        let _ = self::visit_subdir(tx, root, path, opts);
+    })
+}
+
+/// Sends `DirEnter`, all files directly inside `dir` and `DirLeave`, handing every
+/// subdirectory over to `subdir` as it is found, which is announced by `MessageType::Subdir`.
+///
+/// Symlinks are never followed. Symlinks to files are sent with their own size, other symlinks
+/// and special files are skipped unless `opts.special_files` is set, which sends them as files.
+///
+/// With `opts.one_file_system` subdirectories on another device than `dir` are not handed
+/// over, they are sent as `MountPoint` items if `opts.mount_points` is set.
+///
+/// Entries matched by `opts.filter` relative to `root` are skipped. With `opts.count_excluded`
+/// their sizes are summed up and sent as single `Excluded` item named `EXCLUDED_KEY`, which can
+/// not collide with a real entry.
+///
+/// With `opts.previous` the listing of a directory whose device, inode and mtime did not change
+/// since the snapshot is taken from it instead of being read, which is announced by
+/// `MessageType::Reused`. Reused entries go through the same filter and device checks and
+/// subdirectories are still handed over and checked on their own.
+///
+/// Errors are sent as `MessageType::Error` messages, scanning continues with the next entry.
+pub fn scan_dir<F>(tx: &TxChannel,
+                   root: &Path,
+                   dir: &Path,
+                   opts: &OptionsScan,
+                   subdir: &mut F)
+                   -> Result<()>
+    where F: FnMut(&Path)
+{
     debug!("Entering directory {:?}", dir);
 
-    let metadata = fs::symlink_metadata(dir)?;
+    let metadata = fs::symlink_metadata(dir)
+        .map_err(|e| self::send_error(tx, Error::io(dir, e)))?;
     let file_type = metadata.file_type();
 
     if !dir.is_dir() || file_type.is_symlink() {
         return Ok(());
     }
 
-    let dir_path = dir.to_str().unwrap().to_string();
-    let dir_meta = Box::new(dir.metadata().unwrap()) as Box<std::os::unix::fs::MetadataExt>;
+    let dir_path = dir.to_path_buf();
+    let dir_meta = dir.metadata().map_err(|e| self::send_error(tx, Error::io(dir, e)))?;
+    let dir_meta = Box::new(dir_meta) as Box<std::os::unix::fs::MetadataExt>;
 
     let _ = tx.send((MessageType::FsItem,
                      Some(dir_path.clone()),
                      Some(Box::new(FsItemInfo {
         event_type: EventType::DirEnter,
+        dev: dir_meta.dev(),
         ino: dir_meta.ino(),
+        nlink: dir_meta.nlink(),
         mtime: dir_meta.mtime(),
         size: dir_meta.size(),
+        blocks: dir_meta.blocks(),
     }))));
 
-    if let Ok(entries) = fs::read_dir(dir) {
-        for entry in entries {
-            debug!("Processing entry {:?}", entry);
+    let mut excluded: Option<FsItemInfo> = None;
 
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_dir() {
-                    debug!("Processing directory: {:?}", &path);
-                    let _ = self::visit_dir(tx, &path);
-
-                } else if path.is_file() {
-                    debug!("Processing file: {:?}", &path);
-
-                    let file_path = path.to_str().unwrap().to_string();
-                    let _ = tx.send((MessageType::FsItem,
-                                     Some(file_path.clone()),
-                                     Some(self::get_file_info(&EventType::File, &entry))));
+    let mut handover = |path: &Path| {
+        let _ = tx.send((MessageType::Subdir, Some(path.to_path_buf()), None));
+        subdir(path)
+    };
+
+    if let Some(previous) = self::previous_listing(dir, &*dir_meta, opts) {
+        debug!("Reusing listing of {:?}", dir);
+        let _ = tx.send((MessageType::Reused, Some(dir_path.clone()), None));
+        self::reuse_listing(tx,
+                            root,
+                            &dir_path,
+                            dir_meta.dev(),
+                            previous,
+                            opts,
+                            &mut excluded,
+                            &mut handover);
+    } else {
+        match fs::read_dir(dir) {
+            Ok(entries) => {
+                for entry in entries {
+                    debug!("Processing entry {:?}", entry);
+
+                    match entry {
+                        Ok(entry) => {
+                            self::scan_entry(tx,
+                                             root,
+                                             &entry,
+                                             dir_meta.dev(),
+                                             opts,
+                                             &mut excluded,
+                                             &mut handover)
+                        }
+                        Err(e) => {
+                            let _ = self::send_error(tx, Error::io(dir, e));
+                        }
+                    }
                 }
             }
+            Err(e) => {
+                let _ = self::send_error(tx, Error::io(dir, e));
+            }
         }
     }
 
+    if let Some(info) = excluded {
+        let _ = tx.send((MessageType::FsItem,
+                         Some(dir_path.join(EXCLUDED_KEY)),
+                         Some(Box::new(info))));
+    }
+
     let _ = tx.send((MessageType::FsItem,
                      Some(dir_path.clone()),
                      Some(Box::new(FsItemInfo {
         event_type: EventType::DirLeave,
+        dev: dir_meta.dev(),
         ino: dir_meta.ino(),
+        nlink: dir_meta.nlink(),
         mtime: dir_meta.mtime(),
         size: dir_meta.size(),
+        blocks: dir_meta.blocks(),
     }))));
 
     debug!("Leaving directory {:?}", dir);
     Ok(())
 }
 
+fn scan_entry<F>(tx: &TxChannel,
+                 root: &Path,
+                 entry: &DirEntry,
+                 dev: u64,
+                 opts: &OptionsScan,
+                 excluded: &mut Option<FsItemInfo>,
+                 subdir: &mut F)
+    where F: FnMut(&Path)
+{
+    let path = entry.path();
+    let file_type = entry.file_type().ok();
+
+    let (is_dir, is_file) = if opts.special_files {
+        // Symlinks are not followed, they are counted by their own size like other special files
+        let is_dir = file_type.map(|file_type| file_type.is_dir()).unwrap_or(false);
+        (is_dir, !is_dir)
+    } else {
+        // Symlinked directories would not be scanned, so they are not handed over at all
+        let is_symlink = file_type.map(|file_type| file_type.is_symlink()).unwrap_or(false);
+        (path.is_dir() && !is_symlink, path.is_file())
+    };
+
+    if !is_dir && !is_file {
+        return;
+    }
+
+    if self::exclude(root, &path, is_dir, dev, opts, excluded) {
+        return;
+    }
+
+    if is_dir {
+        self::enter_dir(tx, &path, dev, opts, subdir);
+    } else {
+        debug!("Processing file: {:?}", &path);
+        self::send_item(tx, &EventType::File, entry);
+    }
+}
+
+// Checks `path` against `opts.filter`, with `opts.count_excluded` sizes of excluded entries are
+// added up in `excluded`
+fn exclude(root: &Path,
+           path: &Path,
+           is_dir: bool,
+           dev: u64,
+           opts: &OptionsScan,
+           excluded: &mut Option<FsItemInfo>)
+           -> bool {
+    if opts.filter.is_empty() || !self::is_excluded(root, path, is_dir, &opts.filter) {
+        return false;
+    }
+
+    debug!("Excluding: {:?}", path);
+
+    if opts.count_excluded {
+        let (size, blocks) = self::excluded_size(path, dev, opts);
+        let info = excluded.get_or_insert(FsItemInfo {
+            event_type: EventType::Excluded,
+            dev: dev,
+            ino: 0,
+            nlink: 1,
+            mtime: 0,
+            size: 0,
+            blocks: 0,
+        });
+        info.size += size;
+        info.blocks += blocks;
+    }
+
+    true
+}
+
+// Hands `path` over to `subdir`, unless it is on another device than `dev` with
+// `opts.one_file_system`
+fn enter_dir<F>(tx: &TxChannel, path: &Path, dev: u64, opts: &OptionsScan, subdir: &mut F)
+    where F: FnMut(&Path)
+{
+    let md = if opts.one_file_system {
+        fs::symlink_metadata(path).ok()
+    } else {
+        None
+    };
+
+    match md {
+        Some(ref md) if md.dev() != dev => {
+            if opts.mount_points {
+                debug!("Processing mount point: {:?}", path);
+                let _ = tx.send((MessageType::FsItem,
+                                 Some(path.to_path_buf()),
+                                 Some(Box::new(self::metadata_info(&EventType::MountPoint, md)))));
+            } else {
+                debug!("Skipping mount point: {:?}", path);
+            }
+        }
+        _ => {
+            debug!("Processing directory: {:?}", path);
+            subdir(path);
+        }
+    }
+}
+
+// Node of `dir` in the previous snapshot, if the directory was not modified since. Directories
+// modified in the second the snapshot was started may have changed after being listed.
+// Listings with excluded entries are read again, the current filter may differ.
+fn previous_listing<'a>(dir: &Path,
+                        md: &MetadataExt,
+                        opts: &'a OptionsScan)
+                        -> Option<&'a PathCacheInfo> {
+    let previous = opts.previous.as_ref()?;
+    let node = PathCache::find(&previous.tree, &mut path_parts(dir))?;
+
+    let has_excluded = node.childs
+        .as_ref()
+        .map_or(false, |childs| {
+            childs.values().any(|child| match child.data {
+                Some(FsItemInfo { event_type: EventType::Excluded, .. }) => true,
+                _ => false,
+            })
+        });
+    if has_excluded {
+        return None;
+    }
+
+    match node.data {
+        Some(ref info) => {
+            match info.event_type {
+                EventType::DirEnter if info.dev == md.dev() && info.ino == md.ino() &&
+                                       info.mtime == md.mtime() &&
+                                       info.mtime < previous.start_time => Some(node),
+                _ => None,
+            }
+        }
+        None => None,
+    }
+}
+
+// Sends listing of `dir` taken from the previous snapshot, applying the filter and device
+// checks of `scan_entry` to each entry
+fn reuse_listing<F>(tx: &TxChannel,
+                    root: &Path,
+                    dir: &Path,
+                    dev: u64,
+                    node: &PathCacheInfo,
+                    opts: &OptionsScan,
+                    excluded: &mut Option<FsItemInfo>,
+                    subdir: &mut F)
+    where F: FnMut(&Path)
+{
+    let childs = match node.childs {
+        Some(ref childs) => childs,
+        None => return,
+    };
+
+    for child in childs.values() {
+        let path = dir.join(&child.path);
+
+        let info = match child.data {
+            Some(info) => info,
+            None => continue,
+        };
+        let is_dir = match info.event_type {
+            EventType::DirEnter | EventType::DirLeave | EventType::MountPoint => true,
+            _ => false,
+        };
+
+        if self::exclude(root, &path, is_dir, dev, opts, excluded) {
+            continue;
+        }
+
+        if is_dir {
+            self::enter_dir(tx, &path, dev, opts, subdir);
+        } else {
+            let _ = tx.send((MessageType::FsItem, Some(path), Some(Box::new(info))));
+        }
+    }
+}
+
+fn send_item(tx: &TxChannel, event_type: &EventType, entry: &DirEntry) {
+    match self::get_file_info(event_type, entry) {
+        Ok(info) => {
+            let _ = tx.send((MessageType::FsItem, Some(entry.path()), Some(info)));
+        }
+        Err(e) => {
+            let _ = self::send_error(tx, e);
+        }
+    }
+}
+
+/// Sends error to the consumer, returning it back for further propagation
+pub fn send_error(tx: &TxChannel, err: Error) -> Error {
+    warn!("{}", err);
+    let _ = tx.send((MessageType::Error(err.errno()), Some(err.path().to_path_buf()), None));
+    err
+}
+
+fn is_excluded(root: &Path, path: &Path, is_dir: bool, filter: &Filter) -> bool {
+    match path.strip_prefix(root) {
+        Ok(relative) => filter.is_excluded(relative.as_os_str().as_bytes(), is_dir),
+        Err(_) => false,
+    }
+}
+
+// Apparent size and blocks of everything under `path`, not following symlinks
+fn excluded_size(path: &Path, dev: u64, opts: &OptionsScan) -> (u64, u64) {
+    let md = match fs::symlink_metadata(path) {
+        Ok(md) => md,
+        Err(_) => return (0, 0),
+    };
+
+    if !md.is_dir() {
+        return (md.size(), md.blocks());
+    }
+
+    if opts.one_file_system && md.dev() != dev {
+        return (0, 0);
+    }
+
+    let mut res = (md.size(), md.blocks());
+
+    if let Ok(entries) = fs::read_dir(path) {
+        for entry in entries {
+            if let Ok(entry) = entry {
+                let (size, blocks) = self::excluded_size(&entry.path(), dev, opts);
+                res.0 += size;
+                res.1 += blocks;
+            }
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
 mod tests {
+    extern crate tempfile;
+
+    use bincode::{deserialize, serialize, Infinite};
+    use serde_json;
+
+    use std::collections::BTreeMap;
+    use std::ffi::{OsStr, OsString};
+    use std::fs::File;
+    use std::io::Write;
+    use std::os::unix::fs::symlink;
+    use std::sync::Arc;
+
+    use super::*;
+    use snapshot::{Snapshot, VERSION};
+    use types::overall_info::OverallInfo;
+    use types::path_cache::EXCLUDED_NAME;
+
     #[cfg(bench)]
     #[bench]
     fn bench_year_flags_from_year(bh: &mut test::Bencher) {
@@ -110,4 +476,232 @@ mod tests {
             }
         });
     }
+
+    #[test]
+    fn scan_handles_errors_and_non_utf8_names() {
+        let tmp = tempfile::tempdir().unwrap();
+        File::create(tmp.path().join("ok")).unwrap();
+        File::create(tmp.path().join(OsStr::from_bytes(b"latin\xe9"))).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let opts = OptionsScan::default();
+        let _ = visit_dir(&tx, tmp.path(), &opts);
+        let _ = visit_dir(&tx, Path::new("/nonexistent/wims"), &opts);
+        drop(tx);
+
+        let messages = rx.iter().collect::<Vec<_>>();
+        let errnos = messages.iter()
+            .filter_map(|&(ref msg, _, _)| match *msg {
+                MessageType::Error(errno) => Some(errno),
+                _ => None,
+            })
+            .collect::<Vec<i32>>();
+
+        assert_eq!(errnos, vec![libc::ENOENT]);
+        assert!(messages.iter()
+            .any(|&(_, ref path, _)| path.as_ref().unwrap().ends_with(OsStr::from_bytes(b"latin\xe9"))));
+    }
+
+    fn scan(root: &Path,
+            opts: &OptionsScan)
+            -> Vec<(MessageType, Option<PathBuf>, Option<Box<FsItemInfo>>)> {
+        let (tx, rx) = mpsc::channel();
+        let _ = visit_dir(&tx, root, opts);
+        drop(tx);
+        rx.iter().collect()
+    }
+
+    // Snapshot of `root` as scanned now, `modify` can change the tree before it is wrapped
+    fn previous<F>(root: &Path, modify: F) -> Arc<Snapshot>
+        where F: FnOnce(&mut BTreeMap<OsString, PathCacheInfo>)
+    {
+        let mut tree = BTreeMap::new();
+        for (_, path, info) in scan(root, &OptionsScan::default()) {
+            if let (Some(path), Some(info)) = (path, info) {
+                match info.event_type {
+                    EventType::DirLeave => {}
+                    _ => PathCache::construct(&mut tree, &mut path_parts(&path), &*info),
+                }
+            }
+        }
+        modify(&mut tree);
+
+        Arc::new(Snapshot {
+            version: VERSION,
+            roots: vec![root.to_path_buf()],
+            start_time: i64::max_value(),
+            host: OsString::new(),
+            options: Vec::new(),
+            overall: OverallInfo::default(),
+            tree: tree,
+        })
+    }
+
+    #[test]
+    fn scan_reuses_unchanged_listings() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("a/b")).unwrap();
+        File::create(tmp.path().join("a/x")).unwrap();
+        File::create(tmp.path().join("a/b/y")).unwrap();
+
+        // Pretend `a/b` was modified since the snapshot
+        let b = tmp.path().join("a/b");
+        let previous = previous(tmp.path(), |tree| {
+            PathCache::find_mut(tree, &mut path_parts(&b)).unwrap().data.as_mut().unwrap().mtime -= 1;
+        });
+
+        File::create(tmp.path().join("a/new")).unwrap();
+        File::create(tmp.path().join("a/b/new")).unwrap();
+
+        let opts = OptionsScan { previous: Some(previous), ..OptionsScan::default() };
+
+        let messages = scan(tmp.path(), &opts);
+        let reused = messages.iter()
+            .filter_map(|&(ref msg, ref path, _)| match *msg {
+                MessageType::Reused => path.clone(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let has = |path: &str| {
+            messages.iter().any(|&(_, ref p, _)| p.as_ref() == Some(&tmp.path().join(path)))
+        };
+
+        // Listing of `a` is reused, so `a/new` created after the snapshot is not noticed
+        assert!(reused.contains(&tmp.path().join("a")));
+        assert!(!reused.contains(&b));
+        assert!(has("a/x") && has("a/b/y") && has("a/b/new"));
+        assert!(!has("a/new"));
+    }
+
+    #[test]
+    fn scan_reused_listings_are_filtered() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("a/node_modules/lib")).unwrap();
+        File::create(tmp.path().join("a/x")).unwrap();
+        File::create(tmp.path().join("a/node_modules/lib/y")).unwrap().write_all(b"12345").unwrap();
+
+        let mut filter = Filter::new();
+        filter.exclude("node_modules/");
+        let opts = OptionsScan {
+            filter: filter,
+            count_excluded: true,
+            previous: Some(previous(tmp.path(), |_| {})),
+            ..OptionsScan::default()
+        };
+
+        let messages = scan(tmp.path(), &opts);
+        let paths = messages.iter()
+            .filter_map(|&(_, ref path, _)| path.as_ref())
+            .filter_map(|path| path.strip_prefix(tmp.path()).ok())
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+
+        assert!(messages.iter().any(|&(ref msg, ref path, _)| match *msg {
+            MessageType::Reused => path.as_ref() == Some(&tmp.path().join("a")),
+            _ => false,
+        }));
+        assert!(paths.contains(&String::from("a/x")));
+        assert!(!paths.iter().any(|path| path.starts_with("a/node_modules")));
+
+        // Sizes of the excluded directory are still counted
+        let excluded = messages.iter()
+            .filter_map(|&(_, _, ref info)| info.as_ref())
+            .find(|info| match info.event_type {
+                EventType::Excluded => true,
+                _ => false,
+            })
+            .unwrap();
+        assert!(excluded.size >= 5);
+    }
+
+    #[test]
+    fn scan_special_files_only_like_du() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir(tmp.path().join("sub")).unwrap();
+        File::create(tmp.path().join("f")).unwrap();
+        symlink("f", tmp.path().join("l")).unwrap();
+        symlink("sub", tmp.path().join("d")).unwrap();
+        symlink("missing", tmp.path().join("broken")).unwrap();
+
+        let files = |opts: &OptionsScan| {
+            scan(tmp.path(), opts)
+                .into_iter()
+                .filter_map(|(_, path, info)| match info {
+                    Some(ref info) => {
+                        match info.event_type {
+                            EventType::File => Some(path.unwrap()),
+                            _ => None,
+                        }
+                    }
+                    None => path,
+                })
+                .map(|path| path.strip_prefix(tmp.path()).unwrap().to_string_lossy().into_owned())
+                .filter(|path| !path.is_empty() && path != "sub")
+                .collect::<Vec<_>>()
+        };
+
+        // Symlinked directories and dangling symlinks are neither followed nor counted
+        let mut paths = files(&OptionsScan::default());
+        paths.sort();
+        assert_eq!(paths, vec!["f", "l"]);
+
+        let mut paths = files(&OptionsScan { special_files: true, ..OptionsScan::default() });
+        paths.sort();
+        assert_eq!(paths, vec!["broken", "d", "f", "l"]);
+    }
+
+    #[test]
+    fn scan_excluded_item_does_not_collide() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("node_modules")).unwrap();
+        File::create(tmp.path().join("node_modules/y")).unwrap().write_all(b"12345").unwrap();
+        File::create(tmp.path().join(EXCLUDED_NAME)).unwrap().write_all(b"123").unwrap();
+
+        let mut filter = Filter::new();
+        filter.exclude("node_modules/");
+        let opts = OptionsScan {
+            filter: filter,
+            count_excluded: true,
+            ..OptionsScan::default()
+        };
+
+        let mut tree = BTreeMap::new();
+        for (_, path, info) in scan(tmp.path(), &opts) {
+            if let (Some(path), Some(info)) = (path, info) {
+                match info.event_type {
+                    EventType::DirLeave => {}
+                    _ => PathCache::construct(&mut tree, &mut path_parts(&path), &*info),
+                }
+            }
+        }
+
+        let check = |dir: &PathCacheInfo| {
+            let childs = dir.childs.as_ref().unwrap();
+            assert_eq!(childs.len(), 2);
+
+            let real = childs[OsStr::new(EXCLUDED_NAME)].data.unwrap();
+            assert!(match real.event_type {
+                EventType::File => real.size == 3,
+                _ => false,
+            });
+
+            let excluded = &childs[OsStr::new(EXCLUDED_KEY)];
+            assert_eq!(excluded.path, OsStr::new(EXCLUDED_NAME));
+            assert!(match excluded.data.unwrap().event_type {
+                EventType::Excluded => excluded.data.unwrap().size >= 5,
+                _ => false,
+            });
+        };
+
+        let dir = PathCache::find(&tree, &mut path_parts(tmp.path())).unwrap();
+        check(dir);
+
+        // Both items survive a round trip, which keys child nodes by their names
+        let encoded = serialize(&tree, Infinite).unwrap();
+        let decoded: BTreeMap<OsString, PathCacheInfo> = deserialize(&encoded).unwrap();
+        check(PathCache::find(&decoded, &mut path_parts(tmp.path())).unwrap());
+
+        let encoded = serde_json::to_string(dir).unwrap();
+        check(&serde_json::from_str::<PathCacheInfo>(&encoded).unwrap());
+    }
 }
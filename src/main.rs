@@ -9,16 +9,32 @@ extern crate serde;
 extern crate wims;
 extern crate time;
 
-use bincode::{serialize, Infinite};
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
 use std::collections::BTreeMap;
 use std::io;
 use std::io::Write;
 use std::env;
-use std::sync::mpsc;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{mpsc, Arc};
 use std::thread;
+use std::time::SystemTime;
 use time::PreciseTime;
 use wims::*;
+use wims::browse;
+use wims::csv;
+use wims::diff;
+use wims::du;
+use wims::folded;
+use wims::history::History;
+use wims::html;
+use wims::json;
+use wims::ncdu;
+use wims::snapshot::Snapshot;
+use wims::svg;
+use wims::treemap;
 
 use self::types::*;
 
@@ -36,13 +52,112 @@ fn main() {
             .short("v")
             .long("verbose")
             .multiple(true))
-        .arg(Arg::with_name("cache")
-            .help("Cache items to disk")
-            .long("cache"))
+        .arg(Arg::with_name("all")
+            .help("Show files too in --du output")
+            .long("all")
+            .requires("du")
+            .conflicts_with("summarize"))
+        .arg(Arg::with_name("apparent-size")
+            .help("Count apparent sizes of files (default)")
+            .long("apparent-size")
+            .conflicts_with("disk-usage"))
+        .arg(Arg::with_name("block-size")
+            .help("Units of sizes in --du output")
+            .long("block-size")
+            .value_name("SIZE")
+            .requires("du"))
+        .arg(Arg::with_name("chart")
+            .help("Chart drawn by --format svg")
+            .long("chart")
+            .possible_values(&["sunburst", "icicle"])
+            .default_value("sunburst"))
+        .arg(Arg::with_name("db")
+            .help("Record scan into SQLite history database")
+            .long("db")
+            .value_name("FILE"))
+        .arg(Arg::with_name("disk-usage")
+            .help("Count allocated disk blocks instead of apparent sizes")
+            .long("disk-usage"))
+        .arg(Arg::with_name("du")
+            .help("Print sizes of directories like GNU du instead of tree, counts disk usage \
+                   unless --apparent-size is set")
+            .long("du"))
+        .arg(Arg::with_name("count-excluded")
+            .help("Sum sizes of excluded items into <excluded> tree nodes")
+            .long("count-excluded"))
+        .arg(Arg::with_name("errors")
+            .help("List unreadable paths at exit")
+            .long("errors"))
+        .arg(Arg::with_name("exclude")
+            .help("Skip files and directories matching glob pattern")
+            .long("exclude")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("exclude-from")
+            .help("Read exclude patterns from .gitignore style file")
+            .long("exclude-from")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("export-ncdu")
+            .help("Write tree to file in ncdu JSON dump format")
+            .long("export-ncdu")
+            .takes_value(true))
+        .arg(Arg::with_name("format")
+            .help("Tree output format")
+            .long("format")
+            .possible_values(&["text", "json", "json-lines", "csv", "tsv", "svg", "html", "folded"])
+            .default_value("text"))
+        .arg(Arg::with_name("graph-style")
+            .help("Characters used for tree bars")
+            .long("graph-style")
+            .possible_values(&["ascii", "unicode"])
+            .default_value("ascii"))
+        .arg(Arg::with_name("graph-width")
+            .help("Show bars of N characters in tree")
+            .long("graph-width")
+            .value_name("N")
+            .default_value("0"))
+        .arg(Arg::with_name("hard-links")
+            .help("Hard links counting")
+            .long("hard-links")
+            .possible_values(&["global", "subtree", "naive"])
+            .default_value("naive"))
         .arg(Arg::with_name("human")
             .help("Human readable sizes")
             .short("h")
             .long("human"))
+        .arg(Arg::with_name("include")
+            .help("Include items matching glob pattern even if excluded")
+            .long("include")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("load")
+            .help("Load tree from snapshot file or ncdu JSON dump instead of scanning")
+            .long("load")
+            .takes_value(true)
+            .conflicts_with_all(&["DIR", "save"]))
+        .arg(Arg::with_name("max-depth")
+            .help("Show only N levels below roots in --du output")
+            .long("max-depth")
+            .value_name("N")
+            .requires("du")
+            .conflicts_with("summarize"))
+        .arg(Arg::with_name("min-angle")
+            .help("Leave out nodes narrower than DEG degrees from --format svg")
+            .long("min-angle")
+            .value_name("DEG")
+            .default_value("0.5"))
+        .arg(Arg::with_name("mount-points")
+            .help("Show skipped mount points in tree")
+            .long("mount-points")
+            .requires("one-file-system"))
+        .arg(Arg::with_name("one-file-system")
+            .help("Skip directories on different file systems")
+            .short("x")
+            .long("one-file-system"))
         .arg(Arg::with_name("progress")
             .help("Show progress")
             .short("p")
@@ -58,10 +173,46 @@ fn main() {
             .long("progress-format")
             .possible_values(&["dot", "path", "raw"])
             .default_value("path"))
+        .arg(Arg::with_name("reverse")
+            .help("Reverse sort order")
+            .short("r")
+            .long("reverse"))
+        .arg(Arg::with_name("save")
+            .help("Save scanned tree to snapshot file")
+            .long("save")
+            .takes_value(true))
+        .arg(Arg::with_name("since")
+            .help("Reuse listings of directories unchanged since snapshot file")
+            .long("since")
+            .takes_value(true)
+            .conflicts_with("load"))
+        .arg(Arg::with_name("sort")
+            .help("Sort tree levels, sizes, times and counts are sorted biggest first")
+            .long("sort")
+            .possible_values(&["name", "size", "mtime", "count"])
+            .default_value("name"))
         .arg(Arg::with_name("stats")
             .help("Print overall stats at exit")
             .short("s")
             .long("stats"))
+        .arg(Arg::with_name("summarize")
+            .help("Show only roots in --du output")
+            .long("summarize")
+            .requires("du"))
+        .arg(Arg::with_name("threads")
+            .help("Number of scanner threads")
+            .short("j")
+            .long("threads")
+            .default_value("1"))
+        .arg(Arg::with_name("top")
+            .help("List N largest files and directories at exit")
+            .long("top")
+            .value_name("N")
+            .default_value("0"))
+        .arg(Arg::with_name("total")
+            .help("Show grand total in --du output")
+            .long("total")
+            .requires("du"))
         .arg(Arg::with_name("tree")
             .help("Show FS tree")
             .short("t")
@@ -71,6 +222,11 @@ fn main() {
             .short("d")
             .long("tree-depth")
             .default_value("0"))
+        .arg(Arg::with_name("tree-style")
+            .help("Tree drawing style")
+            .long("tree-style")
+            .possible_values(&["ascii", "unicode", "indent"])
+            .default_value("indent"))
         .arg(Arg::with_name("tree-only-dirs")
             .help("Print only directories in tree")
             .long("tree-only-dirs"))
@@ -79,9 +235,84 @@ fn main() {
             .index(1)
             .required(false)
             .multiple(true))
+        .subcommand(SubCommand::with_name("browse")
+            .about("Browse scanned or --load tree interactively in the terminal")
+            .arg(Arg::with_name("DIR")
+                .help("Directories to scan")
+                .index(1)
+                .multiple(true)))
+        .subcommand(SubCommand::with_name("diff")
+            .about("Compare two snapshots saved by --save")
+            .arg(Arg::with_name("human")
+                .help("Human readable sizes")
+                .short("h")
+                .long("human"))
+            .arg(Arg::with_name("threshold")
+                .help("Hide changes smaller than SIZE, e.g. 100M")
+                .long("threshold")
+                .value_name("SIZE")
+                .default_value("0"))
+            .arg(Arg::with_name("tree-depth")
+                .help("Compare only N first tree levels")
+                .short("d")
+                .long("tree-depth")
+                .default_value("0"))
+            .arg(Arg::with_name("OLD")
+                .help("Older snapshot or ncdu JSON dump")
+                .index(1)
+                .required(true))
+            .arg(Arg::with_name("NEW")
+                .help("Newer snapshot or ncdu JSON dump")
+                .index(2)
+                .required(true)))
+        .subcommand(SubCommand::with_name("history")
+            .about("Print size of directory in all scans recorded by --db")
+            .arg(Arg::with_name("db")
+                .help("History database")
+                .long("db")
+                .value_name("FILE")
+                .required(true))
+            .arg(Arg::with_name("human")
+                .help("Human readable sizes")
+                .short("h")
+                .long("human"))
+            .arg(Arg::with_name("PATH")
+                .help("Directory as given to the recorded scans")
+                .index(1)
+                .required(true)))
+        .subcommand(SubCommand::with_name("treemap")
+            .about("Draw treemap of scanned or --load tree into PNG image")
+            .arg(Arg::with_name("color-by")
+                .help("Colour rectangles by tree level or file extension")
+                .long("color-by")
+                .possible_values(&["depth", "extension"])
+                .default_value("depth"))
+            .arg(Arg::with_name("height")
+                .help("Image height in pixels")
+                .long("height")
+                .default_value("768"))
+            .arg(Arg::with_name("output")
+                .help("PNG file to write")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .required(true))
+            .arg(Arg::with_name("tree-depth")
+                .help("Draw only N first tree levels")
+                .short("d")
+                .long("tree-depth")
+                .default_value("0"))
+            .arg(Arg::with_name("width")
+                .help("Image width in pixels")
+                .long("width")
+                .default_value("1024"))
+            .arg(Arg::with_name("DIR")
+                .help("Directories to scan")
+                .index(1)
+                .multiple(true)))
         .get_matches();
 
-    let opts = Options::from(&matches);
+    let mut opts = Options::from(&matches);
 
     match matches.occurrences_of("verbose") {
         0 => {}
@@ -92,86 +323,124 @@ fn main() {
 
     env_logger::init().unwrap();
 
-    let (tx, rx) = mpsc::channel();
-    let handle = create_thread(rx, &opts);
+    if let Some(matches) = matches.subcommand_matches("diff") {
+        diff_snapshots(&OptionsDiff::from(matches), &opts);
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("history") {
+        print_history(&OptionsHistory::from(matches));
+        return;
+    }
+
+    if let Some(ref path) = opts.snapshot.load {
+        load_snapshot(path, &opts);
+        return;
+    }
 
-    let dirs: Vec<_> = match matches.values_of("DIR") {
+    // Subcommands which scan take directories on their own
+    let dir_matches = matches.subcommand_matches("treemap")
+        .or(matches.subcommand_matches("browse"))
+        .unwrap_or(&matches);
+    let dirs: Vec<_> = match dir_matches.values_of_os("DIR") {
         Some(dirs) => {
-            dirs.map(|d| {
-                    if d.len() > 1 {
-                        d.trim_right_matches('/').to_string()
-                    } else {
-                        d.to_string()
-                    }
-                })
-                .collect()
+            dirs.map(trim_path).collect()
         }
-        _ => vec![String::from(".")],
+        _ => vec![PathBuf::from(".")],
     };
 
-    wims::process(&tx, &dirs);
+    if let Some(ref path) = opts.snapshot.since.clone() {
+        match Snapshot::load(path) {
+            Ok(snapshot) => opts.scan.previous = Some(Arc::new(snapshot)),
+            Err(e) => {
+                error!("Unable to load snapshot - {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let handle = create_thread(rx, &dirs, &opts);
+
+    wims::process(&tx, &dirs, &opts);
 
     let _ = tx.send((MessageType::Exit, None, None));
     let _ = handle.join();
 }
 
-fn create_thread(rx: RxChannel, opts: &Options) -> thread::JoinHandle<()> {
+fn create_thread(rx: RxChannel, dirs: &Vec<PathBuf>, opts: &Options) -> thread::JoinHandle<()> {
     let mut stdout = io::stdout();
     let start = PreciseTime::now();
+    let start_time = SystemTime::now();
 
+    let dirs = dirs.clone();
     let opts = opts.clone();
 
     thread::spawn(move || {
         let mut overall = OverallInfo {
             files: 0,
             dirs: 0,
+            dedup_size: 0,
+            errors: 0,
+            reused: 0,
         };
 
-        let mut stack = FsStack::new();
-        let mut dir_files = Vec::new();
-        let mut pc: BTreeMap<String, PathCacheInfo> = BTreeMap::new();
+        let mut errors: Vec<(PathBuf, i32)> = Vec::new();
+        let mut open_dirs = FsDirs::new();
+        let mounts = if opts.scan.mount_points {
+            MountInfo::read_all().unwrap_or_else(|e| {
+                warn!("Unable to read mount points - {:?}", e);
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+        let mut pc: BTreeMap<OsString, PathCacheInfo> = BTreeMap::new();
+        let mut top_files = TopN::new(opts.top.count);
+        let mut top_dirs = TopN::new(opts.top.count);
 
         loop {
             match rx.recv() {
                 Ok(received) => {
-                    let data: (MessageType, Option<String>, Option<Box<FsItemInfo>>) = received;
+                    let data: (MessageType, Option<PathBuf>, Option<Box<FsItemInfo>>) = received;
                     match data.0 {
+                        MessageType::Error(errno) => {
+                            handle_error(&mut overall, &mut errors, data.1.unwrap(), errno, &opts);
+                        }
                         MessageType::FsItem => {
                             let info = data.2.unwrap();
                             // items.push(info.clone());
-                            handle_fs_item(&mut stack,
+                            handle_fs_item(&mut open_dirs,
                                            &mut pc,
+                                           &mounts,
                                            &mut overall,
-                                           &mut dir_files,
+                                           &mut top_files,
+                                           &mut top_dirs,
                                            data.1.unwrap(),
                                            info,
                                            &opts,
                                            &mut stdout);
                         }
+                        MessageType::Reused => {
+                            overall.reused += 1;
+                        }
+                        MessageType::Subdir => {
+                            handle_subdir(&mut open_dirs, &data.1.unwrap());
+                        }
                         MessageType::Exit => {
-                            for (k, v) in pc.iter_mut() {
-                                debug!("Calculating {:?}", k);
-                                v.calculate_size();
-                                debug!("Calculated total_size of topmost directory is {:?}",
-                                       v.total_size());
-                            }
+                            handle_exit(&mut pc, &dirs, &mut overall, &errors, &start, &opts);
 
-                            if opts.cache.enabled {
-                                let encoded: Vec<u8> = serialize(&pc, Infinite).unwrap();
-                                println!("{:?}", encoded);
-
-                                // let decoded: Option<BTreeMap<String, PathCacheInfo>> = deserialize(&encoded[..]).unwrap();
+                            if opts.top.count > 0 {
+                                finish_open_dirs(&mut open_dirs, &mut top_dirs);
+                                print_top(&top_files, &top_dirs, &opts);
                             }
 
-                            if opts.tree.enabled {
-                                PathCache::print(&pc,
-                                                 0,
-                                                 opts.tree.max_depth,
-                                                 opts.tree.only_dirs,
-                                                 opts.human.enabled);
+                            if opts.snapshot.save.is_some() || opts.snapshot.db.is_some() ||
+                               opts.output.ncdu.is_some() {
+                                let snapshot =
+                                    Snapshot::new(&dirs, start_time, &opts, &overall, pc);
+                                save_snapshot(&snapshot, &opts);
                             }
-
-                            handle_exit(&overall, &start, &opts);
                             break;
                         }
                     };
@@ -182,53 +451,140 @@ fn create_thread(rx: RxChannel, opts: &Options) -> thread::JoinHandle<()> {
     })
 }
 
-fn handle_dir_enter(stack: &mut FsStack,
-                    pc: &mut BTreeMap<String, PathCacheInfo>,
+fn handle_dir_enter(open_dirs: &mut FsDirs,
+                    pc: &mut BTreeMap<OsString, PathCacheInfo>,
                     overall: &mut OverallInfo,
-                    dir_files: &mut Vec<Box<Vec<Box<FsItemInfo>>>>,
-                    path: &String,
+                    path: &PathBuf,
                     info: &Box<FsItemInfo>,
                     opts: &Options)
                     -> bool {
     overall.dirs += 1;
 
-    let mut parts = path.split("/")
-        .map(|i| i.to_string())
-        .collect::<Vec<String>>();
-    parts.reverse();
-
-    PathCache::construct(pc, &mut parts, &info.clone());
+    PathCache::construct(pc, &mut path_parts(path), &info.clone());
     // path_cache::print(&pc, 0);
 
     let res = print_progress_if_needed(overall, path, info, opts);
 
-    dir_files.push(Box::new(Vec::new()));
-
     debug!("{:?}", info);
 
-    stack.push(FsDirInfo {
-        path: path.clone(),
-        files: Vec::new(),
-        files_size: 0,
-    });
+    open_dirs.insert(path.clone(),
+                     FsDirInfo {
+                         path: path.clone(),
+                         files: Vec::new(),
+                         files_size: 0,
+                         size: opts.size.mode.size(&**info),
+                         pending: 0,
+                         left: false,
+                     });
 
     res
 }
 
-fn handle_dir_leave(stack: &mut FsStack,
-                    dir_files: &mut Vec<Box<Vec<Box<FsItemInfo>>>>,
-                    path: &String,
-                    _info: &Box<FsItemInfo>) {
+fn handle_dir_leave(open_dirs: &mut FsDirs,
+                    top_dirs: &mut TopN,
+                    path: &PathBuf,
+                    _info: &Box<FsItemInfo>,
+                    opts: &Options) {
+    if let Some(dir) = open_dirs.get_mut(path) {
+        dir.calculate_files_size(opts.size.mode);
+        dir.size += dir.files_size;
+        dir.left = true;
+        debug!("Leaving {:?}: {:?}", path, dir);
+    }
 
-    let _files = dir_files.pop().unwrap();
-    // stack.last_mut().unwrap().files = files;
-    stack.last_mut().unwrap().calculate_files_size();
+    finish_dir(open_dirs, top_dirs, path);
+}
 
-    debug!("Stack when leaving {}: {:?}", path, stack);
-    stack.pop();
+// The walker may leave a directory before its subdirectories are even entered, so parents
+// count subdirectories handed over and wait for them to finish
+fn handle_subdir(open_dirs: &mut FsDirs, path: &PathBuf) {
+    if let Some(parent) = path.parent().and_then(|p| open_dirs.get_mut(p)) {
+        parent.pending += 1;
+    }
 }
 
-fn handle_exit(overall: &OverallInfo, start: &PreciseTime, opts: &Options) {
+// Ranks `path` once its whole subtree is scanned and adds its size to the parent, which may
+// become finished in turn
+fn finish_dir(open_dirs: &mut FsDirs, top_dirs: &mut TopN, path: &Path) {
+    let mut path = path.to_path_buf();
+
+    while open_dirs.get(&path).map_or(false, |dir| dir.is_finished()) {
+        let dir = open_dirs.remove(&path).unwrap();
+        top_dirs.push(dir.size, &path);
+
+        match path.parent().and_then(|p| open_dirs.get_mut(p)) {
+            Some(parent) => {
+                parent.size += dir.size;
+                parent.pending -= 1;
+            }
+            None => return,
+        }
+        path.pop();
+    }
+}
+
+// Subdirectories which could not be entered never finish, directories waiting for them are
+// ranked with what was scanned, deepest first
+fn finish_open_dirs(open_dirs: &mut FsDirs, top_dirs: &mut TopN) {
+    let mut paths = open_dirs.keys().cloned().collect::<Vec<_>>();
+    paths.sort_by_key(|path| ::std::cmp::Reverse(path.components().count()));
+
+    for path in paths.iter() {
+        if let Some(dir) = open_dirs.get_mut(path) {
+            dir.left = true;
+            dir.pending = 0;
+        }
+        finish_dir(open_dirs, top_dirs, path);
+    }
+}
+
+fn handle_error(overall: &mut OverallInfo,
+                errors: &mut Vec<(PathBuf, i32)>,
+                path: PathBuf,
+                errno: i32,
+                opts: &Options) {
+    overall.errors += 1;
+
+    if opts.errors.enabled {
+        errors.push((path, errno));
+    }
+}
+
+fn handle_exit(pc: &mut BTreeMap<OsString, PathCacheInfo>,
+               roots: &Vec<PathBuf>,
+               overall: &mut OverallInfo,
+               errors: &Vec<(PathBuf, i32)>,
+               start: &PreciseTime,
+               opts: &Options) {
+    PathCache::calculate_sizes(pc, opts.size.hard_links, opts.size.mode);
+    overall.dedup_size = pc.values().map(|v| v.dedup_size()).sum();
+
+    if opts.browse {
+        if let Err(e) = browse::run(pc, opts) {
+            error!("Unable to browse tree - {}", e);
+        }
+    } else {
+        match opts.output.format {
+            OutputFormat::Text if opts.du.enabled => print_du(pc, roots, opts),
+            OutputFormat::Text => {
+                if opts.tree.enabled {
+                    PathCache::print(pc, &opts.tree, opts.human.enabled, &opts.sort);
+                }
+            }
+            _ => print_listing(pc, opts),
+        }
+    }
+
+    if let Some(ref treemap_opts) = opts.treemap {
+        if let Err(e) = treemap::save(pc, treemap_opts) {
+            error!("Unable to write treemap - {}", e);
+        }
+    }
+
+    if opts.errors.enabled {
+        print_errors(errors);
+    }
+
     if opts.stats.enabled {
         let diff = start.to(PreciseTime::now());
         let elapsed_secs = diff.num_nanoseconds().unwrap() as f64 * 1e-9;
@@ -236,51 +592,70 @@ fn handle_exit(overall: &OverallInfo, start: &PreciseTime, opts: &Options) {
     }
 }
 
-fn handle_file(pc: &mut BTreeMap<String, PathCacheInfo>,
+fn handle_excluded(pc: &mut BTreeMap<OsString, PathCacheInfo>,
+                   path: &PathBuf,
+                   info: Box<FsItemInfo>) {
+    PathCache::construct(pc, &mut path_parts(path), &info);
+
+    debug!("Excluded {:?}: {:?}", path, info);
+}
+
+fn handle_file(open_dirs: &mut FsDirs,
+               pc: &mut BTreeMap<OsString, PathCacheInfo>,
                overall: &mut OverallInfo,
-               dir_files: &mut Vec<Box<FsItemInfo>>,
-               path: &String,
+               top_files: &mut TopN,
+               path: &PathBuf,
                info: Box<FsItemInfo>,
                opts: &Options)
                -> bool {
     overall.files += 1;
+    top_files.push(opts.size.mode.size(&*info), path);
 
-    let mut parts = path.split("/")
-        .map(|i| i.to_string())
-        .collect::<Vec<String>>();
-    parts.reverse();
-
-    PathCache::construct(pc, &mut parts, &info.clone());
+    PathCache::construct(pc, &mut path_parts(path), &info.clone());
 
     let res = print_progress_if_needed(overall, &path, &info, &opts);
 
     debug!("{:?}", info);
-    dir_files.push(info);
+
+    // Files are always sent between DirEnter and DirLeave of their own directory,
+    // even when the walker interleaves events of different directories.
+    if let Some(dir) = path.parent().and_then(|p| open_dirs.get_mut(p)) {
+        dir.files.push(*info);
+    }
 
     res
 }
 
-fn handle_fs_item(stack: &mut FsStack,
-                  pc: &mut BTreeMap<String, PathCacheInfo>,
+fn handle_fs_item(open_dirs: &mut FsDirs,
+                  pc: &mut BTreeMap<OsString, PathCacheInfo>,
+                  mounts: &Vec<MountInfo>,
                   overall: &mut OverallInfo,
-                  dir_files: &mut Vec<Box<Vec<Box<FsItemInfo>>>>,
-                  path: String,
+                  top_files: &mut TopN,
+                  top_dirs: &mut TopN,
+                  path: PathBuf,
                   info: Box<FsItemInfo>,
                   opts: &Options,
                   stdout: &mut io::Stdout) {
     match info.event_type {
         EventType::DirEnter => {
-            if handle_dir_enter(stack, pc, overall, dir_files, &path, &info, &opts) {
+            if handle_dir_enter(open_dirs, pc, overall, &path, &info, &opts) {
                 let _ = stdout.flush();
             }
         }
         EventType::DirLeave => {
-            handle_dir_leave(stack, dir_files, &path, &info);
+            handle_dir_leave(open_dirs, top_dirs, &path, &info, &opts);
+        }
+        EventType::MountPoint => {
+            handle_mount_point(pc, mounts, &path, info);
+        }
+        EventType::Excluded => {
+            handle_excluded(pc, &path, info);
         }
         EventType::File => {
-            if handle_file(pc,
+            if handle_file(open_dirs,
+                           pc,
                            overall,
-                           dir_files.last_mut().unwrap(),
+                           top_files,
                            &path,
                            info,
                            &opts) {
@@ -290,18 +665,183 @@ fn handle_fs_item(stack: &mut FsStack,
     };
 }
 
-fn print_progress(overall: &OverallInfo, path: &String, info: &Box<FsItemInfo>, opts: &Options) {
+fn handle_mount_point(pc: &mut BTreeMap<OsString, PathCacheInfo>,
+                      mounts: &Vec<MountInfo>,
+                      path: &PathBuf,
+                      info: Box<FsItemInfo>) {
+    let mut parts = path_parts(path);
+    PathCache::construct(pc, &mut parts.clone(), &info);
+
+    let real_path = fs::canonicalize(path).unwrap_or(path.clone());
+
+    if let Some(node) = PathCache::find_mut(pc, &mut parts) {
+        node.mount = MountInfo::find(mounts, &real_path).cloned();
+    }
+
+    debug!("Mount point {:?}: {:?}", path, info);
+}
+
+fn diff_snapshots(diff_opts: &OptionsDiff, opts: &Options) {
+    let load = |path: &PathBuf| match Snapshot::load(path) {
+        Ok(mut snapshot) => {
+            PathCache::calculate_sizes(&mut snapshot.tree, opts.size.hard_links, opts.size.mode);
+            snapshot
+        }
+        Err(e) => {
+            error!("Unable to load snapshot - {}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut old = load(&diff_opts.old);
+    let mut new = load(&diff_opts.new);
+
+    let entries = diff::diff(&old.tree, &new.tree, diff_opts.max_depth, diff_opts.threshold);
+    diff::print(&entries, diff_opts.human);
+
+    println!("Total: {} -> {}, Changes: {}",
+             human_format_if_needed(roots_size(&mut old), diff_opts.human),
+             human_format_if_needed(roots_size(&mut new), diff_opts.human),
+             entries.len());
+}
+
+// Writes snapshot to --save file, records it into --db history and exports it to --export-ncdu
+// file, sizes are already calculated
+fn save_snapshot(snapshot: &Snapshot, opts: &Options) {
+    if let Some(ref path) = opts.snapshot.save {
+        if let Err(e) = snapshot.save(path) {
+            error!("Unable to save snapshot - {}", e);
+        }
+    }
+
+    if let Some(ref path) = opts.snapshot.db {
+        if let Err(e) = History::open(path).and_then(|mut history| history.record(snapshot)) {
+            error!("Unable to record scan - {}", e);
+        }
+    }
+
+    if let Some(ref path) = opts.output.ncdu {
+        let res = fs::File::create(path)
+            .map(io::BufWriter::new)
+            .and_then(|mut file| ncdu::export(&mut file, snapshot).and_then(|_| file.flush()));
+        if let Err(e) = res {
+            error!("Unable to export ncdu dump - {}", Error::io(path, e));
+        }
+    }
+}
+
+fn roots_size(snapshot: &mut Snapshot) -> u64 {
+    let tree = &mut snapshot.tree;
+    snapshot.roots
+        .iter()
+        .filter_map(|root| PathCache::find_mut(tree, &mut path_parts(root)).map(|v| v.total_size()))
+        .sum()
+}
+
+fn load_snapshot(path: &PathBuf, opts: &Options) {
+    let start = PreciseTime::now();
+
+    match Snapshot::load(path) {
+        Ok(mut snapshot) => {
+            debug!("Loaded snapshot of {:?} from {:?}", snapshot.roots, snapshot.host);
+            handle_exit(&mut snapshot.tree,
+                        &snapshot.roots,
+                        &mut snapshot.overall,
+                        &Vec::new(),
+                        &start,
+                        opts);
+            save_snapshot(&snapshot, opts);
+        }
+        Err(e) => {
+            error!("Unable to load snapshot - {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn print_history(history_opts: &OptionsHistory) {
+    let entries = History::open(&history_opts.db)
+        .and_then(|history| history.size_history(&history_opts.path));
+
+    match entries {
+        Ok(entries) => {
+            let mut previous: Option<u64> = None;
+            for entry in entries.iter() {
+                let delta = match previous {
+                    Some(size) if entry.total_size >= size => {
+                        format!(" (+{})",
+                                human_format_if_needed(entry.total_size - size, history_opts.human))
+                    }
+                    Some(size) => {
+                        format!(" (-{})",
+                                human_format_if_needed(size - entry.total_size, history_opts.human))
+                    }
+                    None => String::new(),
+                };
+
+                println!("{} {}{}, Dirs: {}, Files: {}",
+                         time::at(time::Timespec::new(entry.start_time, 0))
+                             .strftime("%Y-%m-%d %H:%M:%S")
+                             .unwrap(),
+                         human_format_if_needed(entry.total_size, history_opts.human),
+                         delta,
+                         entry.dirs,
+                         entry.files);
+                previous = Some(entry.total_size);
+            }
+
+            if entries.is_empty() {
+                println!("No recorded scans of {}",
+                         escape_os_str(history_opts.path.as_os_str()));
+            }
+        }
+        Err(e) => {
+            error!("Unable to read history - {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn print_du(pc: &BTreeMap<OsString, PathCacheInfo>, roots: &Vec<PathBuf>, opts: &Options) {
+    let stdout = io::stdout();
+    if let Err(e) = du::write(&mut stdout.lock(), pc, roots, opts.size.mode, &opts.du) {
+        error!("Unable to write du output - {}", e);
+    }
+}
+
+// Machine readable output of --format other than text
+fn print_listing(pc: &BTreeMap<OsString, PathCacheInfo>, opts: &Options) {
+    let roots = json::nodes(pc, &opts.tree, &opts.sort);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let res = match opts.output.format {
+        OutputFormat::JsonLines => json::write_lines(&mut out, &roots),
+        OutputFormat::Csv => csv::write(&mut out, &roots, csv::Separator::Comma),
+        OutputFormat::Tsv => csv::write(&mut out, &roots, csv::Separator::Tab),
+        OutputFormat::Svg => svg::write(&mut out, &roots, &opts.svg),
+        OutputFormat::Html => html::write(&mut out, &roots, opts.size.mode),
+        OutputFormat::Folded => folded::write(&mut out, pc, opts.size.hard_links, &opts.tree),
+        _ => json::write(&mut out, &roots, opts.size.mode),
+    };
+
+    if let Err(e) = res {
+        error!("Unable to write {:?} output - {}", opts.output.format, e);
+    }
+}
+
+fn print_progress(overall: &OverallInfo, path: &PathBuf, info: &Box<FsItemInfo>, opts: &Options) {
     match opts.progress.format {
         ProgressFormat::Dot => print!("."),
         ProgressFormat::Path => {
-            println!("{} {}", overall.all(), path);
+            println!("{} {}", overall.all(), escape_os_str(path.as_os_str()));
         }
         ProgressFormat::Raw => println!("{} {:?}", overall.all(), info),
     }
 }
 
 fn print_progress_if_needed(overall: &OverallInfo,
-                            path: &String,
+                            path: &PathBuf,
                             info: &Box<FsItemInfo>,
                             opts: &Options)
                             -> bool {
@@ -313,6 +853,29 @@ fn print_progress_if_needed(overall: &OverallInfo,
     }
 }
 
+fn print_errors(errors: &Vec<(PathBuf, i32)>) {
+    println!("Errors: {}", errors.len());
+    for &(ref path, errno) in errors.iter() {
+        println!("  {}: {}",
+                 escape_os_str(path.as_os_str()),
+                 io::Error::from_raw_os_error(errno));
+    }
+}
+
+fn print_top(top_files: &TopN, top_dirs: &TopN, opts: &Options) {
+    let sections = [("Largest files", top_files),
+                    ("Largest directories", top_dirs)];
+
+    for &(title, top) in sections.iter() {
+        println!("{}:", title);
+        for (size, path) in top.sorted() {
+            println!("  {} {}",
+                     human_format_if_needed(size, opts.human.enabled),
+                     escape_os_str(path.as_os_str()));
+        }
+    }
+}
+
 fn print_stats(info: &OverallInfo, elapsed_secs: f64, opts: &Options) {
     let dirs_count = info.dirs;
     let files_count = info.files;
@@ -337,10 +900,20 @@ fn print_stats(info: &OverallInfo, elapsed_secs: f64, opts: &Options) {
         };
     }
 
-    println!("Dirs: {}, Files: {}, Files Per Dir: {:.2}, Time: {:.2}, Speed: {:.2} ips",
+    let reused = if opts.snapshot.since.is_some() {
+        format!(", Reused Dirs: {}", info.reused)
+    } else {
+        String::new()
+    };
+
+    println!("Dirs: {}, Files: {}, Files Per Dir: {:.2}, Time: {:.2}, Speed: {:.2} ips, \
+              Hard Links Dedup: {}, Errors: {}{}",
              dirs_count,
              files_count,
              fpd,
              elapsed_secs,
-             ips);
+             ips,
+             human_format_if_needed(info.dedup_size, opts.human.enabled),
+             info.errors,
+             reused);
 }
@@ -0,0 +1,295 @@
+//! Export and import of ncdu JSON dumps, as written by `ncdu -o` and read by `ncdu -f`.
+//!
+//! ```text
+//! [1, 0, {"progname": "wims", "progver": "0.1.0", "timestamp": 1500000000},
+//!   [{"name": "/root", "asize": 4096, "dsize": 4096, "dev": 2049, "ino": 2},
+//!     {"name": "file", "asize": 10, "dsize": 4096, "ino": 12, "mtime": 1500000000},
+//!     [{"name": "dir", "asize": 4096, "dsize": 4096, "ino": 13}, ...]]]
+//! ```
+//!
+//! Directories are arrays starting with their own info, files are plain objects. Only one
+//! root can be stored, names are written as UTF-8 with invalid bytes replaced by U+FFFD.
+
+use serde_json::{self, Map, Value};
+
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::{Error, Result};
+use snapshot::{Snapshot, VERSION};
+use types::event_type::EventType;
+use types::item_info::{FsItemInfo, ItemSize};
+use types::overall_info::OverallInfo;
+use types::path_cache::{path_parts, PathCache, PathCacheInfo};
+
+const MAJOR_VERSION: u64 = 1;
+const MINOR_VERSION: u64 = 0;
+
+/// Writes tree of the first root of the snapshot, sizes need not be calculated
+pub fn export<W: Write>(out: &mut W, snapshot: &Snapshot) -> io::Result<()> {
+    let root = match snapshot.roots.first() {
+        Some(root) => root,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no root to export")),
+    };
+    if snapshot.roots.len() > 1 {
+        warn!("ncdu dump holds only one root, exporting {:?}", root);
+    }
+
+    let node = match PathCache::find(&snapshot.tree, &mut path_parts(root)) {
+        Some(node) => node,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "root not found")),
+    };
+
+    write!(out,
+           "[{},{},{{\"progname\":\"wims\",\"progver\":{},\"timestamp\":{}}},\n",
+           MAJOR_VERSION,
+           MINOR_VERSION,
+           json_str(env!("CARGO_PKG_VERSION")),
+           snapshot.start_time)?;
+    write_node(out, node, root.as_os_str(), None)?;
+    write!(out, "]\n")
+}
+
+fn write_node<W: Write>(out: &mut W,
+                        node: &PathCacheInfo,
+                        name: &OsStr,
+                        parent_dev: Option<u64>)
+                        -> io::Result<()> {
+    let data = match node.data {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+
+    let mut info = format!("{{\"name\":{},\"asize\":{},\"dsize\":{}",
+                           json_str(&name.to_string_lossy()),
+                           data.size(),
+                           data.disk_size());
+    if parent_dev != Some(data.dev) {
+        info.push_str(&format!(",\"dev\":{}", data.dev));
+    }
+    info.push_str(&format!(",\"ino\":{},\"mtime\":{}", data.ino, data.mtime));
+
+    match data.event_type {
+        EventType::DirEnter => {
+            write!(out, "[{}}}", info)?;
+            if let Some(ref childs) = node.childs {
+                for child in childs.values() {
+                    if child.data.is_some() {
+                        write!(out, ",\n")?;
+                        write_node(out, child, &child.path, Some(data.dev))?;
+                    }
+                }
+            }
+            write!(out, "]")
+        }
+        EventType::File => {
+            if data.nlink > 1 {
+                info.push_str(&format!(",\"hlnkc\":true,\"nlink\":{}", data.nlink));
+            }
+            write!(out, "{}}}", info)
+        }
+        EventType::MountPoint => write!(out, "{},\"excluded\":\"otherfs\"}}", info),
+        EventType::Excluded => write!(out, "{},\"excluded\":\"pattern\"}}", info),
+        EventType::DirLeave => Ok(()),
+    }
+}
+
+fn json_str(val: &str) -> String {
+    serde_json::to_string(val).unwrap()
+}
+
+/// Whether `buf` looks like an ncdu dump rather than a wims snapshot
+pub fn is_dump(buf: &[u8]) -> bool {
+    buf.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'[')
+}
+
+/// Builds snapshot from ncdu dump read from `path`
+pub fn import(path: &Path, buf: &[u8]) -> Result<Snapshot> {
+    let invalid = |reason| Error::InvalidSnapshot(path.to_path_buf(), reason);
+
+    let dump: Value = serde_json::from_slice(buf).map_err(|_| invalid("invalid ncdu dump"))?;
+    let dump = dump.as_array().ok_or(invalid("invalid ncdu dump"))?;
+
+    if dump.len() < 4 || dump[0].as_u64() != Some(MAJOR_VERSION) {
+        return Err(invalid("unsupported ncdu dump version"));
+    }
+
+    let start_time = dump[2].get("timestamp").and_then(|v| v.as_i64()).unwrap_or(0);
+    let root = dump[3].as_array().ok_or(invalid("ncdu dump root is not a directory"))?;
+    let root_path = root.first()
+        .and_then(|info| info.get("name"))
+        .and_then(|name| name.as_str())
+        .map(PathBuf::from)
+        .ok_or(invalid("ncdu dump root has no name"))?;
+
+    let mut importer = Importer {
+        tree: BTreeMap::new(),
+        overall: OverallInfo::default(),
+    };
+    importer.dir(root, &root_path, 0).ok_or(invalid("invalid ncdu dump node"))?;
+
+    Ok(Snapshot {
+        version: VERSION,
+        roots: vec![root_path],
+        start_time: start_time,
+        host: OsString::new(),
+        options: vec![(String::from("source"), String::from("ncdu"))],
+        overall: importer.overall,
+        tree: importer.tree,
+    })
+}
+
+struct Importer {
+    tree: BTreeMap<OsString, PathCacheInfo>,
+    overall: OverallInfo,
+}
+
+impl Importer {
+    fn dir(&mut self, entries: &Vec<Value>, path: &Path, parent_dev: u64) -> Option<()> {
+        let info = entries.first()?.as_object()?;
+        let dev = self.item(info, path, EventType::DirEnter, parent_dev)?;
+        self.overall.dirs += 1;
+
+        for entry in entries[1..].iter() {
+            match *entry {
+                Value::Array(ref childs) => {
+                    let name = childs.first()?.get("name")?.as_str()?;
+                    self.dir(childs, &path.join(name), dev)?;
+                }
+                Value::Object(ref info) => {
+                    let name = info.get("name")?.as_str()?;
+                    let event_type = match info.get("excluded").and_then(|v| v.as_str()) {
+                        Some("otherfs") => EventType::MountPoint,
+                        Some(_) => EventType::Excluded,
+                        None => {
+                            self.overall.files += 1;
+                            EventType::File
+                        }
+                    };
+                    self.item(info, &path.join(name), event_type, dev)?;
+                }
+                _ => return None,
+            }
+        }
+
+        Some(())
+    }
+
+    // Returns device of the item, which is inherited from parent when not present
+    fn item(&mut self,
+            info: &Map<String, Value>,
+            path: &Path,
+            event_type: EventType,
+            parent_dev: u64)
+            -> Option<u64> {
+        let field = |name: &str| info.get(name).and_then(|v| v.as_u64());
+
+        if info.get("read_error").and_then(|v| v.as_bool()) == Some(true) {
+            self.overall.errors += 1;
+        }
+
+        let nlink = match (field("nlink"), info.get("hlnkc").and_then(|v| v.as_bool())) {
+            (Some(nlink), _) => nlink,
+            (None, Some(true)) => 2,
+            _ => 1,
+        };
+
+        let info = FsItemInfo {
+            event_type: event_type,
+            dev: field("dev").unwrap_or(parent_dev),
+            ino: field("ino").unwrap_or(0),
+            nlink: nlink,
+            mtime: info.get("mtime").and_then(|v| v.as_i64()).unwrap_or(0),
+            size: field("asize").unwrap_or(0),
+            blocks: (field("dsize").unwrap_or(0) + 511) / 512,
+        };
+
+        // Hard links without inode would be all counted as one file
+        let info = if info.ino == 0 {
+            FsItemInfo { nlink: 1, ..info }
+        } else {
+            info
+        };
+
+        PathCache::construct(&mut self.tree, &mut path_parts(path), &info);
+        Some(info.dev)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use types::hard_links::HardLinks;
+    use types::size_mode::SizeMode;
+
+    const DUMP: &'static str = r#"[1,2,{"progname":"ncdu","progver":"1.15","timestamp":1600000000},
+[{"name":"/r","asize":4096,"dsize":4096,"dev":5,"ino":1},
+{"name":"x","asize":100,"dsize":4096,"ino":2,"hlnkc":true,"nlink":2},
+[{"name":"a","asize":4096,"dsize":4096,"ino":3,"mtime":7},
+{"name":"y","asize":100,"dsize":4096,"ino":2,"hlnkc":true,"nlink":2},
+{"name":"skip","excluded":"pattern"},
+{"name":"bad","asize":1,"read_error":true,"ino":4}],
+{"name":"mnt","excluded":"otherfs"}]]"#;
+
+    fn size(snapshot: &mut Snapshot, path: &str) -> u64 {
+        PathCache::find_mut(&mut snapshot.tree, &mut path_parts(Path::new(path)))
+            .unwrap()
+            .total_size()
+    }
+
+    #[test]
+    fn import_dump() {
+        let mut snapshot = import(Path::new("dump.json"), DUMP.as_bytes()).unwrap();
+        PathCache::calculate_sizes(&mut snapshot.tree, HardLinks::Global, SizeMode::Apparent);
+
+        assert_eq!(snapshot.roots, vec![PathBuf::from("/r")]);
+        assert_eq!(snapshot.start_time, 1600000000);
+        assert_eq!((snapshot.overall.dirs, snapshot.overall.files, snapshot.overall.errors),
+                   (2, 3, 1));
+        assert_eq!(size(&mut snapshot, "/r"), 4096 + 100 + 4096 + 1);
+        // Hard link `y` is found before `x`
+        assert_eq!(size(&mut snapshot, "/r/a"), 4096 + 100 + 1);
+
+        let a = PathCache::find_mut(&mut snapshot.tree, &mut path_parts(Path::new("/r/a")))
+            .unwrap();
+        assert_eq!(a.data.unwrap().mtime, 7);
+        assert_eq!(a.data.unwrap().dev, 5);
+    }
+
+    #[test]
+    fn export_and_import() {
+        let snapshot = import(Path::new("dump.json"), DUMP.as_bytes()).unwrap();
+
+        let mut buf = Vec::new();
+        export(&mut buf, &snapshot).unwrap();
+        assert!(is_dump(&buf));
+
+        let dump: Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(dump[0], 1);
+        assert_eq!(dump[2]["timestamp"], 1600000000);
+        assert_eq!(dump[3][0]["name"], "/r");
+        assert_eq!(dump[3][0]["dev"], 5);
+        assert!(dump[3][1][0].get("dev").is_none());
+
+        let mut reimported = import(Path::new("dump.json"), &buf).unwrap();
+        let mut original = snapshot.clone();
+        for pc in vec![&mut reimported.tree, &mut original.tree] {
+            PathCache::calculate_sizes(pc, HardLinks::Global, SizeMode::DiskUsage);
+        }
+        assert_eq!(size(&mut reimported, "/r"), size(&mut original, "/r"));
+        assert_eq!(size(&mut reimported, "/r/a"), size(&mut original, "/r/a"));
+        assert_eq!(reimported.overall.files, original.overall.files);
+    }
+
+    #[test]
+    fn reject_invalid_dumps() {
+        assert!(import(Path::new("d"), b"[2,0,{},[{\"name\":\"/\"}]]").is_err());
+        assert!(import(Path::new("d"), b"[1,0,{},{\"name\":\"/\"}]").is_err());
+        assert!(import(Path::new("d"), b"[1,0,{},[{\"name\":\"/\"},3]]").is_err());
+        assert!(!is_dump(b"WIMS"));
+    }
+}
@@ -0,0 +1,472 @@
+//! Snapshot of a finished scan stored on disk.
+//!
+//! All integers are little endian, byte strings are prefixed by their `u64` length.
+//!
+//! ```text
+//! magic    "WIMS"
+//! version  u32
+//! roots    u64 count, bytes each
+//! start    i64 unix time of the scan start
+//! host     bytes
+//! options  u64 count, (bytes name, bytes value) each
+//! overall  u64 dirs, u64 files, u64 errors
+//! nodes    u64 count, node each
+//! checksum u32 CRC-32 of everything above
+//! ```
+//!
+//! Every node holds the full path bytes, `FsItemInfo` fields and optional mount info. Sizes
+//! are not stored, they are calculated again after loading using current options.
+//!
+//! ncdu JSON dumps are loaded as well, see `ncdu::import`.
+
+use libc;
+
+use std::collections::BTreeMap;
+use std::ffi::{CStr, OsString};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Error, Result};
+use ncdu;
+use types::event_type::EventType;
+use types::item_info::FsItemInfo;
+use types::mount_info::MountInfo;
+use types::options::Options;
+use types::overall_info::OverallInfo;
+use types::path_cache::{path_parts, PathCache, PathCacheInfo};
+
+const MAGIC: &'static [u8] = b"WIMS";
+
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub version: u32,
+    pub roots: Vec<PathBuf>,
+    pub start_time: i64,
+    pub host: OsString,
+    pub options: Vec<(String, String)>,
+    pub overall: OverallInfo,
+    pub tree: BTreeMap<OsString, PathCacheInfo>,
+}
+
+impl Snapshot {
+    pub fn new(roots: &Vec<PathBuf>,
+               start_time: SystemTime,
+               opts: &Options,
+               overall: &OverallInfo,
+               tree: BTreeMap<OsString, PathCacheInfo>)
+               -> Snapshot {
+        let start_time = match start_time.duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+
+        Snapshot {
+            version: VERSION,
+            roots: roots.clone(),
+            start_time: start_time,
+            host: hostname(),
+            options: describe_options(opts),
+            overall: *overall,
+            tree: tree,
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        write_u32(&mut buf, self.version);
+
+        write_u64(&mut buf, self.roots.len() as u64);
+        for root in self.roots.iter() {
+            write_bytes(&mut buf, root.as_os_str().as_bytes());
+        }
+
+        write_u64(&mut buf, self.start_time as u64);
+        write_bytes(&mut buf, self.host.as_bytes());
+
+        write_u64(&mut buf, self.options.len() as u64);
+        for &(ref name, ref value) in self.options.iter() {
+            write_bytes(&mut buf, name.as_bytes());
+            write_bytes(&mut buf, value.as_bytes());
+        }
+
+        write_u64(&mut buf, self.overall.dirs);
+        write_u64(&mut buf, self.overall.files);
+        write_u64(&mut buf, self.overall.errors);
+
+        let mut nodes = Vec::new();
+        collect_nodes(&self.tree, 0, &mut Vec::new(), &mut nodes);
+
+        write_u64(&mut buf, nodes.len() as u64);
+        for (path, node) in nodes {
+            write_node(&mut buf, &path, node);
+        }
+
+        let checksum = crc32(&buf);
+        write_u32(&mut buf, checksum);
+
+        File::create(path)
+            .and_then(|mut file| file.write_all(&buf))
+            .map_err(|e| Error::io(path, e))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot> {
+        let path = path.as_ref();
+
+        let mut buf = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut buf))
+            .map_err(|e| Error::io(path, e))?;
+
+        if ncdu::is_dump(&buf) {
+            return ncdu::import(path, &buf);
+        }
+
+        if buf.len() < MAGIC.len() + 8 || &buf[..MAGIC.len()] != MAGIC {
+            return Err(Error::InvalidSnapshot(path.to_path_buf(), "not a wims snapshot"));
+        }
+
+        let (data, checksum) = buf.split_at(buf.len() - 4);
+        if crc32(data) != Reader::new(checksum).u32().unwrap_or(0) {
+            return Err(Error::InvalidSnapshot(path.to_path_buf(), "checksum mismatch"));
+        }
+
+        let data = &data[MAGIC.len()..];
+        if Reader::new(data).u32() != Some(VERSION) {
+            return Err(Error::InvalidSnapshot(path.to_path_buf(), "unsupported version"));
+        }
+
+        Snapshot::parse(data)
+            .ok_or_else(|| Error::InvalidSnapshot(path.to_path_buf(), "truncated snapshot"))
+    }
+
+    fn parse(data: &[u8]) -> Option<Snapshot> {
+        let mut r = Reader::new(data);
+
+        let version = r.u32()?;
+
+        let mut roots = Vec::new();
+        for _ in 0..r.u64()? {
+            roots.push(PathBuf::from(OsString::from_vec(r.bytes()?)));
+        }
+
+        let start_time = r.u64()? as i64;
+        let host = OsString::from_vec(r.bytes()?);
+
+        let mut options = Vec::new();
+        for _ in 0..r.u64()? {
+            let name = String::from_utf8(r.bytes()?).ok()?;
+            let value = String::from_utf8(r.bytes()?).ok()?;
+            options.push((name, value));
+        }
+
+        let overall = OverallInfo {
+            dirs: r.u64()?,
+            files: r.u64()?,
+            errors: r.u64()?,
+            ..OverallInfo::default()
+        };
+
+        let mut tree = BTreeMap::new();
+        for _ in 0..r.u64()? {
+            let (path, info, mount) = read_node(&mut r)?;
+
+            let mut parts = path_parts(&path);
+            PathCache::construct(&mut tree, &mut parts.clone(), &info);
+            if mount.is_some() {
+                if let Some(node) = PathCache::find_mut(&mut tree, &mut parts) {
+                    node.mount = mount;
+                }
+            }
+        }
+
+        Some(Snapshot {
+            version: version,
+            roots: roots,
+            start_time: start_time,
+            host: host,
+            options: options,
+            overall: overall,
+            tree: tree,
+        })
+    }
+}
+
+fn describe_options(opts: &Options) -> Vec<(String, String)> {
+    let mut res = vec![(String::from("hard_links"), format!("{:?}", opts.size.hard_links)),
+                       (String::from("size_mode"), format!("{:?}", opts.size.mode)),
+                       (String::from("threads"), format!("{}", opts.scan.threads)),
+                       (String::from("one_file_system"), format!("{}", opts.scan.one_file_system)),
+                       (String::from("count_excluded"), format!("{}", opts.scan.count_excluded))];
+
+    for rule in opts.scan.filter.rules.iter() {
+        res.push((String::from("filter"), rule.pattern.clone()));
+    }
+
+    res
+}
+
+fn hostname() -> OsString {
+    let mut buf = [0 as libc::c_char; 256];
+    let res = unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) };
+    if res != 0 {
+        return OsString::new();
+    }
+
+    // gethostname() does not guarantee termination when the name is truncated
+    buf[buf.len() - 1] = 0;
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    OsString::from_vec(name.to_bytes().to_vec())
+}
+
+fn collect_nodes<'a>(pc: &'a BTreeMap<OsString, PathCacheInfo>,
+                     depth: usize,
+                     prefix: &mut Vec<u8>,
+                     nodes: &mut Vec<(Vec<u8>, &'a PathCacheInfo)>) {
+    for (k, v) in pc.iter() {
+        let len = prefix.len();
+        if depth > 0 {
+            prefix.push(b'/');
+        }
+        prefix.extend_from_slice(k.as_bytes());
+
+        if v.data.is_some() {
+            nodes.push((prefix.clone(), v));
+        }
+
+        if let Some(ref childs) = v.childs {
+            collect_nodes(childs, depth + 1, prefix, nodes);
+        }
+
+        prefix.truncate(len);
+    }
+}
+
+fn write_node(buf: &mut Vec<u8>, path: &[u8], node: &PathCacheInfo) {
+    let info = node.data.unwrap();
+
+    write_bytes(buf, path);
+    buf.push(match info.event_type {
+        EventType::DirEnter => 0,
+        EventType::DirLeave => 1,
+        EventType::File => 2,
+        EventType::MountPoint => 3,
+        EventType::Excluded => 4,
+    });
+    write_u64(buf, info.dev);
+    write_u64(buf, info.ino);
+    write_u64(buf, info.nlink);
+    write_u64(buf, info.mtime as u64);
+    write_u64(buf, info.size);
+    write_u64(buf, info.blocks);
+
+    match node.mount {
+        Some(ref mount) => {
+            buf.push(1);
+            write_bytes(buf, mount.mount_point.as_os_str().as_bytes());
+            write_bytes(buf, mount.source.as_bytes());
+            write_bytes(buf, mount.fs_type.as_bytes());
+        }
+        None => buf.push(0),
+    }
+}
+
+fn read_node(r: &mut Reader) -> Option<(PathBuf, FsItemInfo, Option<MountInfo>)> {
+    let path = PathBuf::from(OsString::from_vec(r.bytes()?));
+
+    let event_type = match r.u8()? {
+        0 => EventType::DirEnter,
+        1 => EventType::DirLeave,
+        2 => EventType::File,
+        3 => EventType::MountPoint,
+        4 => EventType::Excluded,
+        _ => return None,
+    };
+
+    let info = FsItemInfo {
+        event_type: event_type,
+        dev: r.u64()?,
+        ino: r.u64()?,
+        nlink: r.u64()?,
+        mtime: r.u64()? as i64,
+        size: r.u64()?,
+        blocks: r.u64()?,
+    };
+
+    let mount = match r.u8()? {
+        0 => None,
+        _ => {
+            Some(MountInfo {
+                mount_point: PathBuf::from(OsString::from_vec(r.bytes()?)),
+                source: OsString::from_vec(r.bytes()?),
+                fs_type: String::from_utf8(r.bytes()?).ok()?,
+            })
+        }
+    };
+
+    Some((path, info, mount))
+}
+
+fn write_u32(buf: &mut Vec<u8>, val: u32) {
+    for i in 0..4 {
+        buf.push((val >> (i * 8)) as u8);
+    }
+}
+
+fn write_u64(buf: &mut Vec<u8>, val: u64) {
+    for i in 0..8 {
+        buf.push((val >> (i * 8)) as u8);
+    }
+}
+
+fn write_bytes(buf: &mut Vec<u8>, val: &[u8]) {
+    write_u64(buf, val.len() as u64);
+    buf.extend_from_slice(val);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data: data }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+
+        let (res, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(res)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|b| b.iter().rev().fold(0u32, |acc, b| (acc << 8) | *b as u32))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|b| b.iter().rev().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    fn bytes(&mut self) -> Option<Vec<u8>> {
+        let len = self.u64()?;
+        if len > self.data.len() as u64 {
+            return None;
+        }
+        self.take(len as usize).map(|b| b.to_vec())
+    }
+}
+
+// CRC-32 (IEEE 802.3), bitwise variant is fast enough for snapshot sizes
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use std::collections::BTreeMap;
+    use std::ffi::{OsStr, OsString};
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use types::hard_links::HardLinks;
+    use types::path_cache::fixture;
+    use types::size_mode::SizeMode;
+
+    fn item(event_type: EventType, ino: u64, size: u64) -> FsItemInfo {
+        let mut info = fixture::item(event_type, ino, 1, size);
+        info.mtime = 1500000000;
+        info
+    }
+
+    fn snapshot() -> Snapshot {
+        let mut tree = BTreeMap::new();
+        let items = vec![(&b"/data"[..], item(EventType::DirEnter, 1, 4096)),
+                         (&b"/data/a"[..], item(EventType::File, 2, 100)),
+                         (&b"/data/caf\xe9"[..], item(EventType::File, 3, 200))];
+        for (path, info) in items {
+            let path = Path::new(OsStr::from_bytes(path));
+            PathCache::construct(&mut tree, &mut path_parts(path), &info);
+        }
+
+        Snapshot {
+            version: VERSION,
+            roots: vec![PathBuf::from("/data")],
+            start_time: 1500000000,
+            host: OsString::from("host"),
+            options: vec![(String::from("threads"), String::from("4"))],
+            overall: OverallInfo {
+                dirs: 1,
+                files: 2,
+                ..OverallInfo::default()
+            },
+            tree: tree,
+        }
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("scan.wims");
+
+        snapshot().save(&path).unwrap();
+        let mut loaded = Snapshot::load(&path).unwrap();
+
+        assert_eq!(loaded.roots, vec![PathBuf::from("/data")]);
+        assert_eq!(loaded.host, OsString::from("host"));
+        assert_eq!(loaded.overall.files, 2);
+
+        PathCache::calculate_sizes(&mut loaded.tree, HardLinks::Global, SizeMode::Apparent);
+        let data = &loaded.tree[OsStr::new("")].childs.as_ref().unwrap()[OsStr::new("data")];
+        assert_eq!(data.total_size(), 4396);
+        assert!(data.childs.as_ref().unwrap().contains_key(OsStr::from_bytes(b"caf\xe9")));
+    }
+
+    #[test]
+    fn load_detects_corruption() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("scan.wims");
+        snapshot().save(&path).unwrap();
+
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(20)).unwrap();
+        file.write_all(b"x").unwrap();
+
+        match Snapshot::load(&path) {
+            Err(Error::InvalidSnapshot(_, reason)) => assert_eq!(reason, "checksum mismatch"),
+            res => panic!("unexpected {:?}", res.map(|s| s.roots)),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! SVG chart of the tree. Sunburst draws tree levels as rings around the centre with angles
+//! proportional to sizes, icicle draws them as rows with proportional widths. Every shape has
+//! a `<title>` with path and size, which browsers show on hover.
+
+use std::f64::consts::PI;
+use std::io::{self, Write};
+
+use json::JsonNode;
+use types::chart::Chart;
+use types::formatter::human_format_if_needed;
+use types::options::OptionsSvg;
+
+/// Width of the image, sunburst is square
+const SIZE: f64 = 800.0;
+const ICICLE_ROW: f64 = 40.0;
+
+/// Visible part of a node, angles are in degrees clockwise from the top
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arc {
+    pub path: String,
+    pub size: u64,
+    pub depth: u16,
+    pub start: f64,
+    pub span: f64,
+}
+
+/// Splits full circle among nodes proportionally to their size, nodes narrower than
+/// `min_angle` are left out together with their children
+pub fn arcs(roots: &Vec<JsonNode>, min_angle: f64) -> Vec<Arc> {
+    let total: u64 = roots.iter().map(|node| node.size).sum();
+
+    let mut res = Vec::new();
+    if total > 0 {
+        arcs_level(roots, total, 0.0, 360.0, min_angle, &mut res);
+    }
+    res
+}
+
+fn arcs_level(nodes: &Vec<JsonNode>,
+              parent_size: u64,
+              start: f64,
+              span: f64,
+              min_angle: f64,
+              res: &mut Vec<Arc>) {
+    let mut start = start;
+    for node in nodes.iter() {
+        let node_span = span * node.size as f64 / parent_size as f64;
+        if node.size > 0 && node_span >= min_angle {
+            res.push(Arc {
+                path: node.path.clone(),
+                size: node.size,
+                depth: node.depth,
+                start: start,
+                span: node_span,
+            });
+            arcs_level(&node.children, node.size, start, node_span, min_angle, res);
+        }
+        start += node_span;
+    }
+}
+
+pub fn write<W: Write>(out: &mut W, roots: &Vec<JsonNode>, opts: &OptionsSvg) -> io::Result<()> {
+    let arcs = arcs(roots, opts.min_angle);
+    let levels = arcs.iter().map(|arc| arc.depth + 1).max().unwrap_or(1) as f64;
+
+    let height = match opts.chart {
+        Chart::Sunburst => SIZE,
+        Chart::Icicle => levels * ICICLE_ROW,
+    };
+
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(out,
+             "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+              viewBox=\"0 0 {0} {1}\" font-family=\"sans-serif\" font-size=\"10\">",
+             SIZE,
+             height)?;
+    writeln!(out, "<style>path:hover, rect:hover {{ opacity: 0.7; }}</style>")?;
+    writeln!(out, "<g stroke=\"#fff\" stroke-width=\"0.5\">")?;
+
+    for arc in arcs.iter() {
+        let title = format!("<title>{} ({})</title>",
+                            escape_xml(&arc.path),
+                            human_format_if_needed(arc.size, true));
+        match opts.chart {
+            Chart::Sunburst => {
+                let ring = SIZE / 2.0 / (levels + 0.5);
+                let inner = ring * (0.5 + arc.depth as f64);
+                writeln!(out,
+                         "<path d=\"{}\" fill=\"{}\" fill-rule=\"evenodd\">{}</path>",
+                         sector(arc.start, arc.span, inner, inner + ring),
+                         colour(arc),
+                         title)?;
+            }
+            Chart::Icicle => {
+                let x = arc.start / 360.0 * SIZE;
+                let y = arc.depth as f64 * ICICLE_ROW;
+                let w = arc.span / 360.0 * SIZE;
+                write!(out,
+                       "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\">{}",
+                       x,
+                       y,
+                       w,
+                       ICICLE_ROW,
+                       colour(arc),
+                       title)?;
+                writeln!(out, "</rect>")?;
+                if w >= 40.0 {
+                    writeln!(out,
+                             "<text x=\"{:.2}\" y=\"{:.2}\" stroke=\"none\" \
+                              pointer-events=\"none\">{}</text>",
+                             x + 3.0,
+                             y + ICICLE_ROW / 2.0 + 3.0,
+                             escape_xml(name(&arc.path)))?;
+                }
+            }
+        }
+    }
+
+    writeln!(out, "</g>")?;
+    writeln!(out, "</svg>")
+}
+
+// Path data of ring sector between `inner` and `outer` radius
+fn sector(start: f64, span: f64, inner: f64, outer: f64) -> String {
+    let point = |angle: f64, radius: f64| {
+        let rad = angle * PI / 180.0;
+        format!("{:.2},{:.2}",
+                SIZE / 2.0 + radius * rad.sin(),
+                SIZE / 2.0 - radius * rad.cos())
+    };
+
+    // Arc from a point to itself is not drawn, so full rings are made of two halves
+    if span >= 359.99 {
+        return format!("M {0} A {2:.2} {2:.2} 0 1 1 {1} A {2:.2} {2:.2} 0 1 1 {0} Z \
+                        M {3} A {5:.2} {5:.2} 0 1 0 {4} A {5:.2} {5:.2} 0 1 0 {3} Z",
+                       point(0.0, outer),
+                       point(180.0, outer),
+                       outer,
+                       point(0.0, inner),
+                       point(180.0, inner),
+                       inner);
+    }
+
+    let large = if span > 180.0 { 1 } else { 0 };
+    format!("M {} A {4:.2} {4:.2} 0 {6} 1 {} L {} A {5:.2} {5:.2} 0 {6} 0 {} Z",
+            point(start, outer),
+            point(start + span, outer),
+            point(start + span, inner),
+            point(start, inner),
+            outer,
+            inner,
+            large)
+}
+
+// Hue follows the angle, so that subtrees keep colour of their parents, deeper levels are lighter
+fn colour(arc: &Arc) -> String {
+    let hue = arc.start + arc.span / 2.0;
+    let lightness = (40 + 7 * arc.depth as u32).min(85);
+    format!("hsl({:.0},60%,{}%)", hue, lightness)
+}
+
+fn name(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(pos) if pos + 1 < path.len() => &path[pos + 1..],
+        _ => path,
+    }
+}
+
+/// Escapes markup characters, control characters which XML does not allow are replaced
+pub fn escape_xml(val: &str) -> String {
+    let mut res = String::with_capacity(val.len());
+    for c in val.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            '\t' | '\n' | '\r' => res.push(c),
+            c if (c as u32) < 0x20 => res.push('\u{fffd}'),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(path: &str, depth: u16, size: u64, children: Vec<JsonNode>) -> JsonNode {
+        JsonNode {
+            kind: if children.is_empty() { "file" } else { "dir" },
+            children: children,
+            ..JsonNode::leaf(path, depth, size)
+        }
+    }
+
+    fn tree() -> Vec<JsonNode> {
+        vec![node("/r",
+                  0,
+                  1000,
+                  vec![node("/r/a", 1, 500, vec![node("/r/a/<x>", 2, 500, vec![])]),
+                       node("/r/b", 1, 499, vec![]),
+                       node("/r/tiny", 1, 1, vec![])])]
+    }
+
+    #[test]
+    fn angles_and_pruning() {
+        let summary = |min_angle| {
+            arcs(&tree(), min_angle)
+                .iter()
+                .map(|arc| (arc.path.clone(), arc.start.round(), arc.span.round()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(summary(0.0),
+                   vec![(String::from("/r"), 0.0, 360.0),
+                        (String::from("/r/a"), 0.0, 180.0),
+                        (String::from("/r/a/<x>"), 0.0, 180.0),
+                        (String::from("/r/b"), 180.0, 180.0),
+                        (String::from("/r/tiny"), 360.0, 0.0)]);
+        assert_eq!(summary(1.0).len(), 4);
+        assert_eq!(summary(200.0).len(), 1);
+    }
+
+    #[test]
+    fn charts() {
+        for &chart in [Chart::Sunburst, Chart::Icicle].iter() {
+            let opts = OptionsSvg {
+                chart: chart,
+                min_angle: 1.0,
+            };
+
+            let mut out = Vec::new();
+            write(&mut out, &tree(), &opts).unwrap();
+            let svg = String::from_utf8(out).unwrap();
+
+            let shape = if chart == Chart::Sunburst { "<path " } else { "<rect " };
+            assert_eq!(svg.matches(shape).count(), 4);
+            assert!(svg.contains("<title>/r/a/&lt;x&gt; (500B)</title>"));
+            assert!(!svg.contains("tiny"));
+            assert!(svg.trim_right().ends_with("</svg>"));
+        }
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape_xml("a&b<c>\"d'"), "a&amp;b&lt;c&gt;&quot;d&apos;");
+        assert_eq!(escape_xml("bell\u{7}"), "bell\u{fffd}");
+    }
+}
@@ -0,0 +1,554 @@
+//! Treemap image of the tree, laid out by the squarified algorithm of Bruls, Huizing and
+//! van Wijk. Every node is a rectangle whose area is proportional to its size, directories
+//! hold rectangles of their children below a band with their name.
+
+use image::{Rgb, RgbImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_hollow_rect_mut};
+use imageproc::rect::Rect as PixelRect;
+
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsString;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+
+use super::{Error, Result};
+use types::color_by::ColorBy;
+use types::event_type::EventType;
+use types::options::OptionsTreemap;
+use types::path_cache::{descend, PathCacheInfo};
+
+/// Glyphs of the built-in font are 5x8 pixels with one pixel of spacing
+const GLYPH_WIDTH: u32 = 6;
+const GLYPH_HEIGHT: u32 = 8;
+/// Height of the band with directory name
+const LABEL_HEIGHT: f64 = 11.0;
+
+const BACKGROUND: [u8; 3] = [32, 32, 32];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl Rect {
+    pub fn area(&self) -> f64 {
+        self.w * self.h
+    }
+
+    fn inset(&self, left: f64, top: f64, right: f64, bottom: f64) -> Rect {
+        Rect {
+            x: self.x + left,
+            y: self.y + top,
+            w: (self.w - left - right).max(0.0),
+            h: (self.h - top - bottom).max(0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub rect: Rect,
+    pub depth: u16,
+    pub name: String,
+    pub is_dir: bool,
+}
+
+/// Splits `rect` into rectangles with areas proportional to `sizes`, which have to be sorted
+/// biggest first. Rows of rectangles are added while they make the worst aspect ratio better.
+pub fn squarify(sizes: &[f64], rect: Rect) -> Vec<Rect> {
+    let total: f64 = sizes.iter().sum();
+    if total <= 0.0 || rect.area() <= 0.0 {
+        return sizes.iter().map(|_| Rect { w: 0.0, h: 0.0, ..rect }).collect();
+    }
+
+    let scale = rect.area() / total;
+    let areas = sizes.iter().map(|size| size * scale).collect::<Vec<_>>();
+
+    let mut res = Vec::with_capacity(areas.len());
+    let mut rect = rect;
+    let mut start = 0;
+    while start < areas.len() {
+        let side = rect.w.min(rect.h);
+
+        let mut end = start + 1;
+        while end < areas.len() &&
+              worst(&areas[start..end + 1], side) <= worst(&areas[start..end], side) {
+            end += 1;
+        }
+
+        let row = &areas[start..end];
+        let row_area: f64 = row.iter().sum();
+        if rect.w >= rect.h {
+            // Column at the left side
+            let w = if rect.h > 0.0 { row_area / rect.h } else { 0.0 };
+            let mut y = rect.y;
+            for area in row.iter() {
+                let h = if w > 0.0 { area / w } else { 0.0 };
+                res.push(Rect { x: rect.x, y: y, w: w, h: h });
+                y += h;
+            }
+            rect = rect.inset(w, 0.0, 0.0, 0.0);
+        } else {
+            // Row at the top side
+            let h = if rect.w > 0.0 { row_area / rect.w } else { 0.0 };
+            let mut x = rect.x;
+            for area in row.iter() {
+                let w = if h > 0.0 { area / h } else { 0.0 };
+                res.push(Rect { x: x, y: rect.y, w: w, h: h });
+                x += w;
+            }
+            rect = rect.inset(0.0, h, 0.0, 0.0);
+        }
+
+        start = end;
+    }
+
+    res
+}
+
+// Worst aspect ratio of rectangles of `row` laid along side of length `side`
+fn worst(row: &[f64], side: f64) -> f64 {
+    let sum: f64 = row.iter().sum();
+    let max = row.iter().cloned().fold(0.0, f64::max);
+    let min = row.iter().cloned().fold(f64::INFINITY, f64::min);
+    if sum <= 0.0 || min <= 0.0 || side <= 0.0 {
+        return f64::INFINITY;
+    }
+
+    let side2 = side * side;
+    let sum2 = sum * sum;
+    (side2 * max / sum2).max(sum2 / (side2 * min))
+}
+
+/// Lays out scanned roots of the tree with calculated sizes over `width` x `height` pixels.
+/// Parents come before their children.
+pub fn layout(pc: &BTreeMap<OsString, PathCacheInfo>,
+              width: u32,
+              height: u32,
+              max_depth: u16)
+              -> Vec<Tile> {
+    let mut roots = Vec::new();
+    collect_roots(pc, Path::new(""), &mut roots);
+
+    let mut tiles = Vec::new();
+    let canvas = Rect {
+        x: 0.0,
+        y: 0.0,
+        w: width as f64,
+        h: height as f64,
+    };
+    layout_level(roots, 0, canvas, 0, max_depth, &mut tiles);
+    tiles
+}
+
+// Path prefixes above the scanned roots are not drawn, roots are labelled by their full path
+fn collect_roots<'a>(pc: &'a BTreeMap<OsString, PathCacheInfo>,
+                     prefix: &Path,
+                     res: &mut Vec<(&'a PathCacheInfo, String)>) {
+    for v in pc.values() {
+        let path = if prefix.as_os_str().is_empty() && v.path.is_empty() {
+            Path::new("/").to_path_buf()
+        } else {
+            prefix.join(&v.path)
+        };
+
+        match v.data {
+            Some(_) => res.push((v, path.to_string_lossy().into_owned())),
+            None => {
+                if let Some(ref childs) = v.childs {
+                    collect_roots(childs, &path, res);
+                }
+            }
+        }
+    }
+}
+
+// `rest` is size of the parent not covered by the nodes, it takes space which is left empty
+fn layout_level(mut nodes: Vec<(&PathCacheInfo, String)>,
+                rest: u64,
+                rect: Rect,
+                depth: u16,
+                max_depth: u16,
+                tiles: &mut Vec<Tile>) {
+    nodes.retain(|&(v, _)| v.total_size() > 0);
+    nodes.sort_by(|a, b| b.0.total_size().cmp(&a.0.total_size()).then_with(|| a.1.cmp(&b.1)));
+
+    // Empty space for the rest keeps its place in the order of sizes, but is not drawn
+    let mut sizes = nodes.iter().map(|&(v, _)| v.total_size() as f64).collect::<Vec<_>>();
+    let rest_index = nodes.iter().position(|&(v, _)| v.total_size() < rest).unwrap_or(nodes.len());
+    if rest > 0 {
+        sizes.insert(rest_index, rest as f64);
+    }
+
+    let rects = squarify(&sizes, rect);
+
+    for (i, (v, name)) in nodes.into_iter().enumerate() {
+        let rect = if rest > 0 && i >= rest_index {
+            rects[i + 1]
+        } else {
+            rects[i]
+        };
+
+        let is_dir = match v.data.map(|data| data.event_type) {
+            Some(EventType::DirEnter) => true,
+            _ => false,
+        };
+        tiles.push(Tile {
+            rect: rect,
+            depth: depth,
+            name: name,
+            is_dir: is_dir,
+        });
+
+        if !is_dir || !descend(depth + 1, max_depth) {
+            continue;
+        }
+
+        let childs = match v.childs {
+            Some(ref childs) => childs,
+            None => continue,
+        };
+
+        // Children go below the name when there is space for both
+        let top = if rect.h >= LABEL_HEIGHT * 3.0 { LABEL_HEIGHT } else { 1.0 };
+        let inner = rect.inset(1.0, top, 1.0, 1.0);
+        if inner.w < 2.0 || inner.h < 2.0 {
+            continue;
+        }
+
+        let childs = childs.values()
+            .filter(|child| child.data.is_some())
+            .map(|child| (child, child.path.to_string_lossy().into_owned()))
+            .collect::<Vec<_>>();
+        let childs_size: u64 = childs.iter().map(|&(child, _)| child.total_size()).sum();
+        layout_level(childs,
+                     v.total_size().saturating_sub(childs_size),
+                     inner,
+                     depth + 1,
+                     max_depth,
+                     tiles);
+    }
+}
+
+/// Draws tiles with names on the ones large enough to hold them
+pub fn render(tiles: &Vec<Tile>, width: u32, height: u32, color_by: ColorBy) -> RgbImage {
+    let mut img = RgbImage::from_pixel(width, height, Rgb(BACKGROUND));
+
+    for tile in tiles.iter() {
+        let x0 = tile.rect.x.round() as i32;
+        let y0 = tile.rect.y.round() as i32;
+        let x1 = (tile.rect.x + tile.rect.w).round() as i32;
+        let y1 = (tile.rect.y + tile.rect.h).round() as i32;
+        if x1 <= x0 || y1 <= y0 {
+            continue;
+        }
+
+        let rect = PixelRect::at(x0, y0).of_size((x1 - x0) as u32, (y1 - y0) as u32);
+        let fill = colour(tile, color_by);
+        draw_filled_rect_mut(&mut img, rect, Rgb(fill));
+        draw_hollow_rect_mut(&mut img, rect, Rgb(shade(fill, 0.6)));
+
+        let chars = ((x1 - x0 - 4).max(0) as u32 / GLYPH_WIDTH) as usize;
+        if (y1 - y0) as u32 >= GLYPH_HEIGHT + 3 && chars >= 3 {
+            let name = fit(&tile.name, chars);
+            draw_text(&mut img, x0 + 2, y0 + 2, &name, text_colour(fill));
+        }
+    }
+
+    img
+}
+
+pub fn save(pc: &BTreeMap<OsString, PathCacheInfo>, opts: &OptionsTreemap) -> Result<()> {
+    let tiles = layout(pc, opts.width, opts.height, opts.max_depth);
+    render(&tiles, opts.width, opts.height, opts.color_by)
+        .save(&opts.output)
+        .map_err(|e| Error::io(&opts.output, io::Error::new(io::ErrorKind::Other, e.to_string())))
+}
+
+fn colour(tile: &Tile, color_by: ColorBy) -> [u8; 3] {
+    match color_by {
+        ColorBy::Depth => {
+            let hue = (tile.depth as f64 * 47.0) % 360.0;
+            hsv(hue, 0.55, if tile.is_dir { 0.65 } else { 0.85 })
+        }
+        ColorBy::Extension => {
+            let extension = if tile.is_dir {
+                None
+            } else {
+                Path::new(&tile.name).extension().map(|ext| ext.to_string_lossy().to_lowercase())
+            };
+
+            match extension {
+                Some(ext) => {
+                    let mut hasher = DefaultHasher::new();
+                    ext.hash(&mut hasher);
+                    hsv((hasher.finish() % 360) as f64, 0.6, 0.85)
+                }
+                None => {
+                    let level = 0.35 + 0.08 * (tile.depth % 5) as f64;
+                    hsv(0.0, 0.0, if tile.is_dir { level } else { 0.75 })
+                }
+            }
+        }
+    }
+}
+
+fn hsv(hue: f64, saturation: f64, value: f64) -> [u8; 3] {
+    let c = value * saturation;
+    let x = c * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = value - c;
+    [((r + m) * 255.0).round() as u8, ((g + m) * 255.0).round() as u8, ((b + m) * 255.0).round() as u8]
+}
+
+fn shade(rgb: [u8; 3], factor: f64) -> [u8; 3] {
+    [(rgb[0] as f64 * factor) as u8, (rgb[1] as f64 * factor) as u8, (rgb[2] as f64 * factor) as u8]
+}
+
+fn text_colour(background: [u8; 3]) -> [u8; 3] {
+    let luma = 0.299 * background[0] as f64 + 0.587 * background[1] as f64 +
+               0.114 * background[2] as f64;
+    if luma > 128.0 { [0, 0, 0] } else { [255, 255, 255] }
+}
+
+// Shortens `name` to `chars` characters, marking the cut by `~`
+fn fit(name: &str, chars: usize) -> String {
+    if name.chars().count() <= chars {
+        name.to_string()
+    } else {
+        let mut res = name.chars().take(chars - 1).collect::<String>();
+        res.push('~');
+        res
+    }
+}
+
+fn draw_text(img: &mut RgbImage, x: i32, y: i32, text: &str, colour: [u8; 3]) {
+    for (i, c) in text.chars().enumerate() {
+        let glyph = match c as u32 {
+            code @ 0x20..=0x7e => &FONT[(code - 0x20) as usize],
+            _ => &FONT[('?' as u32 - 0x20) as usize],
+        };
+
+        for (col, bits) in glyph.iter().enumerate() {
+            for row in 0..GLYPH_HEIGHT {
+                if bits & (1 << row) == 0 {
+                    continue;
+                }
+
+                let px = x + (i as u32 * GLYPH_WIDTH) as i32 + col as i32;
+                let py = y + row as i32;
+                if px >= 0 && py >= 0 && (px as u32) < img.width() && (py as u32) < img.height() {
+                    img.put_pixel(px as u32, py as u32, Rgb(colour));
+                }
+            }
+        }
+    }
+}
+
+/// 5x8 glyphs of printable ASCII, one byte per column with the top row in the lowest bit
+const FONT: [[u8; 5]; 95] = [[0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+                             [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+                             [0x00, 0x07, 0x00, 0x07, 0x00], // "
+                             [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+                             [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+                             [0x23, 0x13, 0x08, 0x64, 0x62], // %
+                             [0x36, 0x49, 0x56, 0x20, 0x50], // &
+                             [0x00, 0x08, 0x07, 0x03, 0x00], // '
+                             [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+                             [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+                             [0x2a, 0x1c, 0x7f, 0x1c, 0x2a], // *
+                             [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+                             [0x00, 0x80, 0x70, 0x30, 0x00], // ,
+                             [0x08, 0x08, 0x08, 0x08, 0x08], // -
+                             [0x00, 0x00, 0x60, 0x60, 0x00], // .
+                             [0x20, 0x10, 0x08, 0x04, 0x02], // /
+                             [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+                             [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+                             [0x72, 0x49, 0x49, 0x49, 0x46], // 2
+                             [0x21, 0x41, 0x49, 0x4d, 0x33], // 3
+                             [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+                             [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+                             [0x3c, 0x4a, 0x49, 0x49, 0x31], // 6
+                             [0x41, 0x21, 0x11, 0x09, 0x07], // 7
+                             [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+                             [0x46, 0x49, 0x49, 0x29, 0x1e], // 9
+                             [0x00, 0x00, 0x14, 0x00, 0x00], // :
+                             [0x00, 0x40, 0x34, 0x00, 0x00], // ;
+                             [0x00, 0x08, 0x14, 0x22, 0x41], // <
+                             [0x14, 0x14, 0x14, 0x14, 0x14], // =
+                             [0x00, 0x41, 0x22, 0x14, 0x08], // >
+                             [0x02, 0x01, 0x59, 0x09, 0x06], // ?
+                             [0x3e, 0x41, 0x5d, 0x59, 0x4e], // @
+                             [0x7c, 0x12, 0x11, 0x12, 0x7c], // A
+                             [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+                             [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+                             [0x7f, 0x41, 0x41, 0x41, 0x3e], // D
+                             [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+                             [0x7f, 0x09, 0x09, 0x09, 0x01], // F
+                             [0x3e, 0x41, 0x41, 0x51, 0x73], // G
+                             [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+                             [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+                             [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+                             [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+                             [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+                             [0x7f, 0x02, 0x1c, 0x02, 0x7f], // M
+                             [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+                             [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+                             [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+                             [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+                             [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+                             [0x26, 0x49, 0x49, 0x49, 0x32], // S
+                             [0x03, 0x01, 0x7f, 0x01, 0x03], // T
+                             [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+                             [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+                             [0x3f, 0x40, 0x38, 0x40, 0x3f], // W
+                             [0x63, 0x14, 0x08, 0x14, 0x63], // X
+                             [0x03, 0x04, 0x78, 0x04, 0x03], // Y
+                             [0x61, 0x59, 0x49, 0x4d, 0x43], // Z
+                             [0x00, 0x7f, 0x41, 0x41, 0x41], // [
+                             [0x02, 0x04, 0x08, 0x10, 0x20], // \
+                             [0x00, 0x41, 0x41, 0x41, 0x7f], // ]
+                             [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+                             [0x40, 0x40, 0x40, 0x40, 0x40], // _
+                             [0x00, 0x03, 0x07, 0x08, 0x00], // `
+                             [0x20, 0x54, 0x54, 0x78, 0x40], // a
+                             [0x7f, 0x28, 0x44, 0x44, 0x38], // b
+                             [0x38, 0x44, 0x44, 0x44, 0x28], // c
+                             [0x38, 0x44, 0x44, 0x28, 0x7f], // d
+                             [0x38, 0x54, 0x54, 0x54, 0x18], // e
+                             [0x00, 0x08, 0x7e, 0x09, 0x02], // f
+                             [0x18, 0xa4, 0xa4, 0x9c, 0x78], // g
+                             [0x7f, 0x08, 0x04, 0x04, 0x78], // h
+                             [0x00, 0x44, 0x7d, 0x40, 0x00], // i
+                             [0x20, 0x40, 0x40, 0x3d, 0x00], // j
+                             [0x7f, 0x10, 0x28, 0x44, 0x00], // k
+                             [0x00, 0x41, 0x7f, 0x40, 0x00], // l
+                             [0x7c, 0x04, 0x78, 0x04, 0x78], // m
+                             [0x7c, 0x08, 0x04, 0x04, 0x78], // n
+                             [0x38, 0x44, 0x44, 0x44, 0x38], // o
+                             [0xfc, 0x18, 0x24, 0x24, 0x18], // p
+                             [0x18, 0x24, 0x24, 0x18, 0xfc], // q
+                             [0x7c, 0x08, 0x04, 0x04, 0x08], // r
+                             [0x48, 0x54, 0x54, 0x54, 0x24], // s
+                             [0x04, 0x04, 0x3f, 0x44, 0x24], // t
+                             [0x3c, 0x40, 0x40, 0x20, 0x7c], // u
+                             [0x1c, 0x20, 0x40, 0x20, 0x1c], // v
+                             [0x3c, 0x40, 0x30, 0x40, 0x3c], // w
+                             [0x44, 0x28, 0x10, 0x28, 0x44], // x
+                             [0x4c, 0x90, 0x90, 0x90, 0x7c], // y
+                             [0x44, 0x64, 0x54, 0x4c, 0x44], // z
+                             [0x00, 0x08, 0x36, 0x41, 0x00], // {
+                             [0x00, 0x00, 0x77, 0x00, 0x00], // |
+                             [0x00, 0x41, 0x36, 0x08, 0x00], // }
+                             [0x02, 0x01, 0x02, 0x04, 0x02]]; // ~
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use image;
+
+    use super::*;
+    use types::path_cache::fixture;
+
+    fn tree() -> BTreeMap<OsString, PathCacheInfo> {
+        fixture::tree(&[("/r", EventType::DirEnter, 0),
+                        ("/r/a", EventType::DirEnter, 0),
+                        ("/r/a/x.txt", EventType::File, 600),
+                        ("/r/a/y.txt", EventType::File, 200),
+                        ("/r/b", EventType::DirEnter, 0),
+                        ("/r/b/z.png", EventType::File, 100),
+                        ("/r/c", EventType::File, 100),
+                        ("/r/empty", EventType::File, 0)])
+    }
+
+    fn rect(w: f64, h: f64) -> Rect {
+        Rect {
+            x: 0.0,
+            y: 0.0,
+            w: w,
+            h: h,
+        }
+    }
+
+    #[test]
+    fn squarified_areas() {
+        // Example from the paper
+        let sizes = [6.0, 6.0, 4.0, 3.0, 2.0, 2.0, 1.0];
+        let rects = squarify(&sizes, rect(6.0, 4.0));
+
+        assert_eq!(rects.len(), sizes.len());
+        for (r, size) in rects.iter().zip(sizes.iter()) {
+            assert!((r.area() - size).abs() < 1e-9);
+            assert!(r.x >= 0.0 && r.y >= 0.0 && r.x + r.w <= 6.0 + 1e-9 && r.y + r.h <= 4.0 + 1e-9);
+        }
+
+        // First two are stacked at the left side as 3x2 rectangles
+        assert_eq!(rects[0], rect(3.0, 2.0));
+        assert_eq!(rects[1], Rect { y: 2.0, ..rect(3.0, 2.0) });
+    }
+
+    #[test]
+    fn layout_respects_depth() {
+        let pc = tree();
+
+        let tiles = layout(&pc, 400, 300, 0);
+        let names = tiles.iter().map(|t| (t.depth, t.name.as_str())).collect::<Vec<_>>();
+        assert_eq!(names,
+                   vec![(0, "/r"), (1, "a"), (2, "x.txt"), (2, "y.txt"), (1, "b"), (2, "z.png"),
+                        (1, "c")]);
+        assert_eq!(tiles[0].rect, rect(400.0, 300.0));
+
+        let a = tiles[1].rect.area();
+        let c = tiles[6].rect.area();
+        assert!((a / c - 8.0).abs() < 1e-6);
+
+        let tiles = layout(&pc, 400, 300, 2);
+        assert_eq!(tiles.iter().map(|t| t.depth).max(), Some(1));
+    }
+
+    #[test]
+    fn render_png() {
+        let pc = tree();
+        let tmp = tempfile::tempdir().unwrap();
+        let output = tmp.path().join("treemap.png");
+
+        for &color_by in [ColorBy::Depth, ColorBy::Extension].iter() {
+            let opts = OptionsTreemap {
+                output: output.clone(),
+                width: 320,
+                height: 200,
+                color_by: color_by,
+                max_depth: 0,
+            };
+            save(&pc, &opts).unwrap();
+
+            let img = image::open(&output).unwrap().to_rgb8();
+            assert_eq!(img.dimensions(), (320, 200));
+            assert!(img.pixels().any(|p| p.0 != BACKGROUND));
+        }
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(fit("short", 10), "short");
+        assert_eq!(fit("longer name", 5), "long~");
+
+        let mut img = RgbImage::from_pixel(20, 10, Rgb([0, 0, 0]));
+        draw_text(&mut img, 0, 0, "|", [255, 255, 255]);
+        let lit = (0..10).filter(|&y| img.get_pixel(2, y).0 == [255, 255, 255]).count();
+        assert_eq!(lit, 6);
+    }
+}
@@ -0,0 +1,21 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Chart {
+    /// Rings around the roots, angle proportional to size
+    Sunburst,
+    /// Rows of tree levels, width proportional to size
+    Icicle,
+}
+
+impl From<String> for Chart {
+    fn from(val: String) -> Chart {
+        let val = val.to_lowercase();
+        if val == String::from("sunburst") {
+            Chart::Sunburst
+        } else if val == String::from("icicle") {
+            Chart::Icicle
+        } else {
+            warn!("Invalid chart specified - {:?} - using Chart::Sunburst", val);
+            Chart::Sunburst
+        }
+    }
+}
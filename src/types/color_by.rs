@@ -0,0 +1,21 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorBy {
+    /// Each tree level has its own colour
+    Depth,
+    /// Files with the same extension share colour
+    Extension,
+}
+
+impl From<String> for ColorBy {
+    fn from(val: String) -> ColorBy {
+        let val = val.to_lowercase();
+        if val == String::from("depth") {
+            ColorBy::Depth
+        } else if val == String::from("extension") {
+            ColorBy::Extension
+        } else {
+            warn!("Invalid colouring specified - {:?} - using ColorBy::Depth", val);
+            ColorBy::Depth
+        }
+    }
+}
@@ -1,17 +1,31 @@
+use std::path::PathBuf;
+
 use super::item_info::FsItemInfo;
+use super::size_mode::SizeMode;
 
 #[derive(Debug)]
 pub struct FsDirInfo {
-    pub path: String,
+    pub path: PathBuf,
     // pub dirs: Vec<FsDirInfo>,
     pub files: Vec<FsItemInfo>,
     pub files_size: u64,
+    /// Size of the directory, its files and subdirectories finished so far
+    pub size: u64,
+    /// Subdirectories handed over and not finished yet
+    pub pending: u64,
+    /// `DirLeave` arrived, all files are known
+    pub left: bool,
 }
 
 impl FsDirInfo {
-    pub fn calculate_files_size(&mut self) {
+    pub fn calculate_files_size(&mut self, size_mode: SizeMode) {
         for file in self.files.iter() {
-            self.files_size += file.size;
+            self.files_size += size_mode.size(file);
         }
     }
+
+    /// Whole subtree was scanned, `size` is final
+    pub fn is_finished(&self) -> bool {
+        self.left && self.pending == 0
+    }
 }
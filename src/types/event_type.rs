@@ -1,6 +1,66 @@
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use std::fmt;
+
 #[derive(Copy, Clone, Debug)]
 pub enum EventType {
     DirEnter,
     DirLeave,
     File,
+    MountPoint,
+    Excluded,
+}
+
+const NAMES: &'static [&'static str] = &["DirEnter", "DirLeave", "File", "MountPoint", "Excluded"];
+
+impl EventType {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EventType::DirEnter => "DirEnter",
+            EventType::DirLeave => "DirLeave",
+            EventType::File => "File",
+            EventType::MountPoint => "MountPoint",
+            EventType::Excluded => "Excluded",
+        }
+    }
+}
+
+impl Serialize for EventType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for EventType {
+    fn deserialize<D>(deserializer: D) -> Result<EventType, D::Error>
+        where D: Deserializer<'de>
+    {
+        struct EventTypeVisitor;
+
+        impl<'de> Visitor<'de> for EventTypeVisitor {
+            type Value = EventType;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("event type name")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<EventType, E>
+                where E: de::Error
+            {
+                match value {
+                    "DirEnter" => Ok(EventType::DirEnter),
+                    "DirLeave" => Ok(EventType::DirLeave),
+                    "File" => Ok(EventType::File),
+                    "MountPoint" => Ok(EventType::MountPoint),
+                    "Excluded" => Ok(EventType::Excluded),
+                    _ => Err(de::Error::unknown_variant(value, NAMES)),
+                }
+            }
+        }
+
+        deserializer.deserialize_str(EventTypeVisitor)
+    }
 }
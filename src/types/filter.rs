@@ -0,0 +1,203 @@
+use regex;
+use regex::bytes::Regex;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct FilterRule {
+    pub pattern: String,
+    pub regex: Regex,
+    pub negated: bool,
+    pub dir_only: bool,
+}
+
+impl FilterRule {
+    /// Compiles a gitignore style pattern
+    ///
+    /// - `!` prefix negates the pattern, matching paths are included again
+    /// - `/` suffix matches directories only
+    /// - patterns containing `/` are anchored at the scanned directory, others match
+    ///   the name at any depth
+    /// - `*`, `?` and `[...]` do not match `/`, `**` matches across directories
+    pub fn parse(pattern: &str) -> Option<FilterRule> {
+        let mut glob = pattern;
+
+        let negated = glob.starts_with('!');
+        if negated {
+            glob = &glob[1..];
+        }
+
+        let dir_only = glob.ends_with('/');
+        if dir_only {
+            glob = glob.trim_right_matches('/');
+        }
+
+        if glob.is_empty() {
+            return None;
+        }
+
+        let anchored = glob.contains('/');
+        let glob = glob.trim_left_matches('/');
+
+        // Byte oriented matching, so that paths need not be valid UTF-8
+        let prefix = if anchored { "(?-u)^" } else { "(?-u)^(?:.*/)?" };
+        let source = format!("{}{}$", prefix, glob_to_regex(glob));
+
+        match Regex::new(&source) {
+            Ok(regex) => {
+                Some(FilterRule {
+                    pattern: pattern.to_string(),
+                    regex: regex,
+                    negated: negated,
+                    dir_only: dir_only,
+                })
+            }
+            Err(e) => {
+                warn!("Invalid pattern specified - {:?} - {}", pattern, e);
+                None
+            }
+        }
+    }
+
+    pub fn matches(&self, path: &[u8], is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && self.regex.is_match(path)
+    }
+}
+
+/// Ordered list of rules, the last matching rule decides whether a path is excluded.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub rules: Vec<FilterRule>,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter { rules: Vec::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn exclude(&mut self, pattern: &str) {
+        if let Some(rule) = FilterRule::parse(pattern) {
+            self.rules.push(rule);
+        }
+    }
+
+    pub fn include(&mut self, pattern: &str) {
+        self.exclude(&format!("!{}", pattern.trim_left_matches('!')));
+    }
+
+    /// Reads patterns from `.gitignore` style file, skipping blank lines and `#` comments
+    pub fn exclude_from<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let file = File::open(path)?;
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let line = line.trim_right();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // `\#` and `\!` escape a leading special character
+            if line.starts_with("\\#") || line.starts_with("\\!") {
+                if let Some(mut rule) = FilterRule::parse(&line[1..]) {
+                    rule.negated = false;
+                    self.rules.push(rule);
+                }
+            } else {
+                self.exclude(line);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks `path` relative to the scanned directory, using `/` as separator
+    pub fn is_excluded(&self, path: &[u8], is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .map(|rule| !rule.negated)
+            .unwrap_or(false)
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let chars = glob.chars().collect::<Vec<char>>();
+    let mut res = String::new();
+
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if i + 1 < chars.len() && chars[i + 1] == '*' => {
+                if i + 2 < chars.len() && chars[i + 2] == '/' {
+                    res.push_str("(?:.*/)?");
+                    i += 3;
+                } else {
+                    res.push_str(".*");
+                    i += 2;
+                }
+                continue;
+            }
+            '*' => res.push_str("[^/]*"),
+            '?' => res.push_str("[^/]"),
+            '[' => {
+                match chars[i..].iter().position(|c| *c == ']') {
+                    Some(end) if end > 1 => {
+                        let class = chars[i + 1..i + end].iter().cloned().collect::<String>();
+                        let class = if class.starts_with('!') {
+                            format!("^{}", &class[1..])
+                        } else {
+                            class
+                        };
+                        res.push_str(&format!("[{}]", class.replace("\\", "\\\\")));
+                        i += end + 1;
+                        continue;
+                    }
+                    _ => res.push_str("\\["),
+                }
+            }
+            '\\' if i + 1 < chars.len() => {
+                res.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 2;
+                continue;
+            }
+            c => res.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+
+    #[test]
+    fn gitignore_semantics() {
+        let mut filter = Filter::new();
+        filter.exclude("node_modules/");
+        filter.exclude("/proc");
+        filter.exclude("*.log");
+        filter.exclude("build/**/*.o");
+        filter.include("keep.log");
+
+        assert!(filter.is_excluded(b"node_modules", true));
+        assert!(filter.is_excluded(b"a/b/node_modules", true));
+        assert!(!filter.is_excluded(b"a/node_modules", false));
+        assert!(filter.is_excluded(b"proc", true));
+        assert!(!filter.is_excluded(b"a/proc", true));
+        assert!(filter.is_excluded(b"a/b/c.log", false));
+        assert!(!filter.is_excluded(b"a/b/keep.log", false));
+        assert!(filter.is_excluded(b"build/x.o", false));
+        assert!(filter.is_excluded(b"build/a/b/x.o", false));
+        assert!(!filter.is_excluded(b"src/x.o", false));
+        assert!(filter.is_excluded(b"caf\xe9.log", false));
+    }
+}
@@ -1,8 +1,19 @@
 #[cfg(test)]
 extern crate quickcheck;
 
+use ansi_term::Colour;
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::str;
+
+use super::graph_style::GraphStyle;
+
 const UNITS: [&str; 5] = ["", "K", "M", "G", "T"];
 
+// Partial blocks from 1/8 to 7/8 of a character cell
+const EIGHTHS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
 pub fn human_format(val: f32) -> (f32, &'static str) {
     let mut val = val;
 
@@ -19,6 +30,111 @@ pub fn human_format(val: f32) -> (f32, &'static str) {
     return (val, &UNITS[i]);
 }
 
+pub fn human_format_if_needed(size: u64, human_readable: bool) -> String {
+    match human_readable {
+        true => {
+            let (val, unit) = human_format(size as f32);
+            if val == val.floor() {
+                format!("{}{}B", val as u64, unit)
+            } else {
+                format!("{:.2}{}B", val, unit)
+            }
+
+        }
+        false => format!("{}", size),
+    }
+}
+
+/// Bar of `width` characters filled proportionally to `fraction`, padded with spaces
+pub fn bar(fraction: f64, width: usize, style: GraphStyle) -> String {
+    let fraction = fraction.max(0.0).min(1.0);
+    let mut res = String::with_capacity(width * 3);
+
+    match style {
+        GraphStyle::Ascii => {
+            let filled = (fraction * width as f64).round() as usize;
+            res.push_str(&"#".repeat(filled));
+        }
+        GraphStyle::Unicode => {
+            let eighths = (fraction * width as f64 * 8.0).round() as usize;
+            res.push_str(&"█".repeat(eighths / 8));
+            if eighths % 8 > 0 {
+                res.push(EIGHTHS[eighths % 8 - 1]);
+            }
+        }
+    }
+
+    let len = res.chars().count();
+    res.push_str(&" ".repeat(width - len));
+    res
+}
+
+/// Colour of bar by its fill, big shares stand out
+pub fn bar_colour(fraction: f64) -> Colour {
+    if fraction >= 0.5 {
+        Colour::Red
+    } else if fraction >= 0.1 {
+        Colour::Yellow
+    } else {
+        Colour::Green
+    }
+}
+
+/// Parses sizes like `4096`, `1.5M` or `200GB`, units are powers of 1024 as in `human_format`
+pub fn parse_size(val: &str) -> Option<u64> {
+    let val = val.trim();
+    let val = val.trim_right_matches(|c| c == 'B' || c == 'b');
+    let split = val.find(|c: char| c.is_alphabetic()).unwrap_or(val.len());
+    let (number, unit) = val.split_at(split);
+
+    let exp = UNITS.iter().position(|u| u.eq_ignore_ascii_case(unit))?;
+    let number = number.trim().parse::<f64>().ok()?;
+    if number < 0.0 {
+        return None;
+    }
+
+    Some((number * 1024f64.powi(exp as i32)) as u64)
+}
+
+/// Makes path printable on terminal and in text formats. Bytes which are not valid UTF-8
+/// and control characters are written as `\xNN`, backslash as `\\`.
+pub fn escape_os_str(val: &OsStr) -> String {
+    let mut res = String::new();
+    let mut rest = val.as_bytes();
+
+    loop {
+        match str::from_utf8(rest) {
+            Ok(valid) => {
+                escape_str_into(valid, &mut res);
+                return res;
+            }
+            Err(e) => {
+                let (valid, invalid) = rest.split_at(e.valid_up_to());
+                escape_str_into(str::from_utf8(valid).unwrap(), &mut res);
+
+                let len = e.error_len().unwrap_or(invalid.len());
+                for byte in &invalid[..len] {
+                    res.push_str(&format!("\\x{:02x}", byte));
+                }
+                rest = &invalid[len..];
+            }
+        }
+    }
+}
+
+fn escape_str_into(val: &str, res: &mut String) {
+    for c in val.chars() {
+        if c == '\\' {
+            res.push_str("\\\\");
+        } else if c.is_control() && (c as u32) < 0x100 {
+            res.push_str(&format!("\\x{:02x}", c as u32));
+        } else if c.is_control() {
+            res.extend(c.escape_unicode());
+        } else {
+            res.push(c);
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -30,6 +146,36 @@ mod tests {
         rev
     }
 
+    #[test]
+    fn escape_os_str() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(super::escape_os_str(OsStr::new("plain žluťoučký")), "plain žluťoučký");
+        assert_eq!(super::escape_os_str(OsStr::from_bytes(b"caf\xe9 a\\b\n")),
+                   "caf\\xe9 a\\\\b\\x0a");
+    }
+
+    #[test]
+    fn bar() {
+        use types::graph_style::GraphStyle;
+
+        assert_eq!(super::bar(0.5, 10, GraphStyle::Ascii), "#####     ");
+        assert_eq!(super::bar(1.5, 4, GraphStyle::Ascii), "####");
+        assert_eq!(super::bar(0.0, 3, GraphStyle::Unicode), "   ");
+        assert_eq!(super::bar(0.3, 4, GraphStyle::Unicode), "█▎  ");
+    }
+
+    #[test]
+    fn parse_size() {
+        assert_eq!(super::parse_size("4096"), Some(4096));
+        assert_eq!(super::parse_size("1.5K"), Some(1536));
+        assert_eq!(super::parse_size("200GB"), Some(200 * 1024 * 1024 * 1024));
+        assert_eq!(super::parse_size("2 m"), Some(2 * 1024 * 1024));
+        assert_eq!(super::parse_size("-1"), None);
+        assert_eq!(super::parse_size("12X"), None);
+    }
+
     quickcheck! {
       fn prop(xs: Vec<u32>) -> bool {
           xs == reverse(&reverse(&xs))
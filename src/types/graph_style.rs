@@ -0,0 +1,21 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphStyle {
+    /// `[#####     ]`
+    Ascii,
+    /// Block characters with 1/8 resolution
+    Unicode,
+}
+
+impl From<String> for GraphStyle {
+    fn from(val: String) -> GraphStyle {
+        let val = val.to_lowercase();
+        if val == String::from("ascii") {
+            GraphStyle::Ascii
+        } else if val == String::from("unicode") {
+            GraphStyle::Unicode
+        } else {
+            warn!("Invalid graph style specified - {:?} - using GraphStyle::Ascii", val);
+            GraphStyle::Ascii
+        }
+    }
+}
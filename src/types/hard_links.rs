@@ -0,0 +1,26 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HardLinks {
+    /// Every inode is counted once in the whole scan
+    Global,
+    /// Every inode is counted once in each directory subtree
+    Subtree,
+    /// Every link is counted
+    Naive,
+}
+
+impl From<String> for HardLinks {
+    fn from(val: String) -> HardLinks {
+        let val = val.to_lowercase();
+        if val == String::from("global") {
+            HardLinks::Global
+        } else if val == String::from("subtree") {
+            HardLinks::Subtree
+        } else if val == String::from("naive") {
+            HardLinks::Naive
+        } else {
+            warn!("Invalid hard links mode specified - {:?} - using HardLinks::Naive",
+                  val);
+            HardLinks::Naive
+        }
+    }
+}
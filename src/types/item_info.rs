@@ -1,18 +1,29 @@
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
 use serde::ser::{Serialize, Serializer, SerializeStruct};
 
+use std::fmt;
+
 use super::event_type::EventType;
 
 pub trait ItemSize {
     fn event_type(&self) -> &EventType;
     fn size(&self) -> u64;
+    fn disk_size(&self) -> u64;
+    fn mtime(&self) -> i64;
+
+    /// `(dev, ino)` of files having more than one hard link
+    fn link_id(&self) -> Option<(u64, u64)>;
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct FsItemInfo {
     pub event_type: EventType,
+    pub dev: u64,
     pub ino: u64,
+    pub nlink: u64,
     pub mtime: i64,
     pub size: u64,
+    pub blocks: u64,
 }
 
 impl ItemSize for FsItemInfo {
@@ -23,16 +34,153 @@ impl ItemSize for FsItemInfo {
     fn size(&self) -> u64 {
         self.size
     }
+
+    // st_blocks is always counted in 512 byte units
+    fn disk_size(&self) -> u64 {
+        self.blocks * 512
+    }
+
+    fn mtime(&self) -> i64 {
+        self.mtime
+    }
+
+    fn link_id(&self) -> Option<(u64, u64)> {
+        match self.event_type {
+            EventType::File if self.nlink > 1 => Some((self.dev, self.ino)),
+            _ => None,
+        }
+    }
 }
 
 impl Serialize for FsItemInfo {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where S: Serializer
     {
-        let mut s = serializer.serialize_struct("FsItemInfo", 3)?;
+        let mut s = serializer.serialize_struct("FsItemInfo", 7)?;
+        s.serialize_field("event_type", &self.event_type)?;
+        s.serialize_field("dev", &self.dev)?;
         s.serialize_field("ino", &self.ino)?;
+        s.serialize_field("nlink", &self.nlink)?;
         s.serialize_field("mtime", &self.mtime)?;
         s.serialize_field("size", &self.size)?;
+        s.serialize_field("blocks", &self.blocks)?;
         s.end()
     }
 }
+
+const FIELDS: &'static [&'static str] = &["event_type", "dev", "ino", "nlink", "mtime", "size",
+                                          "blocks"];
+
+impl<'de> Deserialize<'de> for FsItemInfo {
+    fn deserialize<D>(deserializer: D) -> Result<FsItemInfo, D::Error>
+        where D: Deserializer<'de>
+    {
+        deserializer.deserialize_struct("FsItemInfo", FIELDS, FsItemInfoVisitor)
+    }
+}
+
+struct FsItemInfoVisitor;
+
+impl<'de> Visitor<'de> for FsItemInfoVisitor {
+    type Value = FsItemInfo;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("struct FsItemInfo")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<FsItemInfo, A::Error>
+        where A: SeqAccess<'de>
+    {
+        macro_rules! next {
+            ($i:expr) => (seq.next_element()?.ok_or_else(|| de::Error::invalid_length($i, &self))?)
+        }
+
+        Ok(FsItemInfo {
+            event_type: next!(0),
+            dev: next!(1),
+            ino: next!(2),
+            nlink: next!(3),
+            mtime: next!(4),
+            size: next!(5),
+            blocks: next!(6),
+        })
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<FsItemInfo, A::Error>
+        where A: MapAccess<'de>
+    {
+        let mut event_type = None;
+        let (mut dev, mut ino, mut nlink, mut mtime, mut size, mut blocks) =
+            (None, None, None, None, None, None);
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "event_type" => event_type = Some(map.next_value()?),
+                "dev" => dev = Some(map.next_value()?),
+                "ino" => ino = Some(map.next_value()?),
+                "nlink" => nlink = Some(map.next_value()?),
+                "mtime" => mtime = Some(map.next_value()?),
+                "size" => size = Some(map.next_value()?),
+                "blocks" => blocks = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(FsItemInfo {
+            event_type: event_type.ok_or_else(|| de::Error::missing_field("event_type"))?,
+            dev: dev.ok_or_else(|| de::Error::missing_field("dev"))?,
+            ino: ino.ok_or_else(|| de::Error::missing_field("ino"))?,
+            nlink: nlink.ok_or_else(|| de::Error::missing_field("nlink"))?,
+            mtime: mtime.ok_or_else(|| de::Error::missing_field("mtime"))?,
+            size: size.ok_or_else(|| de::Error::missing_field("size"))?,
+            blocks: blocks.ok_or_else(|| de::Error::missing_field("blocks"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bincode::{deserialize, serialize, Infinite};
+    use serde_json;
+
+    use super::FsItemInfo;
+    use types::event_type::EventType;
+
+    fn item(kind: u8, dev: u64, ino: u64, nlink: u64, mtime: i64, size: u64) -> FsItemInfo {
+        FsItemInfo {
+            event_type: match kind % 5 {
+                0 => EventType::DirEnter,
+                1 => EventType::DirLeave,
+                2 => EventType::File,
+                3 => EventType::MountPoint,
+                _ => EventType::Excluded,
+            },
+            dev: dev,
+            ino: ino,
+            nlink: nlink,
+            mtime: mtime,
+            size: size,
+            blocks: size / 512,
+        }
+    }
+
+    quickcheck! {
+        fn roundtrip_bincode(kind: u8, dev: u64, ino: u64, nlink: u64, mtime: i64, size: u64)
+                             -> bool {
+            let info = item(kind, dev, ino, nlink, mtime, size);
+            let encoded = serialize(&info, Infinite).unwrap();
+            let decoded: FsItemInfo = deserialize(&encoded).unwrap();
+            serialize(&decoded, Infinite).unwrap() == encoded
+        }
+
+        fn roundtrip_json(kind: u8, dev: u64, ino: u64, nlink: u64, mtime: i64, size: u64)
+                          -> bool {
+            let info = item(kind, dev, ino, nlink, mtime, size);
+            let encoded = serde_json::to_string(&info).unwrap();
+            let decoded: FsItemInfo = serde_json::from_str(&encoded).unwrap();
+            serde_json::to_string(&decoded).unwrap() == encoded
+        }
+    }
+}
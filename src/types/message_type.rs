@@ -1,5 +1,12 @@
 #[derive(Debug)]
 pub enum MessageType {
+    /// Item could not be read, carries errno
+    Error(i32),
     Exit,
     FsItem,
+    /// Listing of directory was taken over from previous snapshot
+    Reused,
+    /// Directory was handed over to be scanned, sent by the listing of its parent before
+    /// any event of the directory itself
+    Subdir,
 }
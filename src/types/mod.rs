@@ -1,19 +1,41 @@
+pub mod chart;
+pub mod color_by;
 pub mod dir_info;
 pub mod event_type;
+pub mod filter;
 pub mod formatter;
+pub mod graph_style;
+pub mod hard_links;
 pub mod item_info;
 pub mod message_type;
+pub mod mount_info;
 pub mod options;
+pub mod output_format;
 pub mod overall_info;
 pub mod path_cache;
 pub mod progress_format;
+pub mod size_mode;
+pub mod sort_by;
+pub mod top_n;
+pub mod tree_style;
 
+pub use self::chart::*;
+pub use self::color_by::*;
 pub use self::dir_info::*;
 pub use self::event_type::*;
+pub use self::filter::*;
 pub use self::formatter::*;
+pub use self::graph_style::*;
+pub use self::hard_links::*;
 pub use self::item_info::*;
 pub use self::message_type::*;
+pub use self::mount_info::*;
 pub use self::options::*;
+pub use self::output_format::*;
 pub use self::overall_info::*;
 pub use self::path_cache::*;
 pub use self::progress_format::*;
+pub use self::size_mode::*;
+pub use self::sort_by::*;
+pub use self::top_n::*;
+pub use self::tree_style::*;
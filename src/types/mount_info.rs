@@ -0,0 +1,173 @@
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer, SerializeStruct};
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+const MOUNTINFO_PATH: &'static str = "/proc/self/mountinfo";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MountInfo {
+    pub mount_point: PathBuf,
+    pub source: OsString,
+    pub fs_type: String,
+}
+
+impl MountInfo {
+    pub fn read_all() -> io::Result<Vec<MountInfo>> {
+        let file = File::open(MOUNTINFO_PATH)?;
+
+        let mut mounts = Vec::new();
+        for line in BufReader::new(file).split(b'\n') {
+            if let Some(mount) = MountInfo::parse(&line?) {
+                mounts.push(mount);
+            }
+        }
+
+        Ok(mounts)
+    }
+
+    /// Parses one line of `/proc/self/mountinfo`, see proc(5)
+    ///
+    /// `36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue`
+    pub fn parse(line: &[u8]) -> Option<MountInfo> {
+        let separator = line.windows(3).position(|w| w == b" - ")?;
+        let fields = line[..separator].split(|b| *b == b' ').collect::<Vec<&[u8]>>();
+        let tail = line[separator + 3..].split(|b| *b == b' ').collect::<Vec<&[u8]>>();
+
+        if fields.len() < 5 || tail.len() < 2 {
+            return None;
+        }
+
+        Some(MountInfo {
+            mount_point: PathBuf::from(unescape(fields[4])),
+            source: unescape(tail[1]),
+            fs_type: String::from_utf8_lossy(tail[0]).into_owned(),
+        })
+    }
+
+    pub fn find<'a>(mounts: &'a Vec<MountInfo>, path: &Path) -> Option<&'a MountInfo> {
+        // Later mounts hide earlier ones on the same mount point
+        mounts.iter().rev().find(|m| m.mount_point == path)
+    }
+}
+
+impl Serialize for MountInfo {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("MountInfo", 3)?;
+        s.serialize_field("mount_point", &self.mount_point.as_os_str().as_bytes())?;
+        s.serialize_field("source", &self.source.as_bytes())?;
+        s.serialize_field("fs_type", &self.fs_type)?;
+        s.end()
+    }
+}
+
+const FIELDS: &'static [&'static str] = &["mount_point", "source", "fs_type"];
+
+impl<'de> Deserialize<'de> for MountInfo {
+    fn deserialize<D>(deserializer: D) -> Result<MountInfo, D::Error>
+        where D: Deserializer<'de>
+    {
+        deserializer.deserialize_struct("MountInfo", FIELDS, MountInfoVisitor)
+    }
+}
+
+struct MountInfoVisitor;
+
+impl<'de> Visitor<'de> for MountInfoVisitor {
+    type Value = MountInfo;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("struct MountInfo")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<MountInfo, A::Error>
+        where A: SeqAccess<'de>
+    {
+        let mount_point: Vec<u8> =
+            seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let source: Vec<u8> =
+            seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let fs_type = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+
+        Ok(MountInfo {
+            mount_point: PathBuf::from(OsString::from_vec(mount_point)),
+            source: OsString::from_vec(source),
+            fs_type: fs_type,
+        })
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<MountInfo, A::Error>
+        where A: MapAccess<'de>
+    {
+        let (mut mount_point, mut source, mut fs_type) = (None, None, None);
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "mount_point" => mount_point = Some(map.next_value::<Vec<u8>>()?),
+                "source" => source = Some(map.next_value::<Vec<u8>>()?),
+                "fs_type" => fs_type = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let mount_point = mount_point.ok_or_else(|| de::Error::missing_field("mount_point"))?;
+        let source = source.ok_or_else(|| de::Error::missing_field("source"))?;
+
+        Ok(MountInfo {
+            mount_point: PathBuf::from(OsString::from_vec(mount_point)),
+            source: OsString::from_vec(source),
+            fs_type: fs_type.ok_or_else(|| de::Error::missing_field("fs_type"))?,
+        })
+    }
+}
+
+// Space, tab, newline and backslash are escaped as octal `\ooo`
+fn unescape(bytes: &[u8]) -> OsString {
+    let mut res = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 3 < bytes.len() &&
+           bytes[i + 1..i + 4].iter().all(|b| *b >= b'0' && *b <= b'7') {
+            let code = bytes[i + 1..i + 4]
+                .iter()
+                .fold(0u8, |acc, b| acc.wrapping_mul(8).wrapping_add(*b - b'0'));
+            res.push(code);
+            i += 4;
+        } else {
+            res.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    OsString::from_vec(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+
+    use super::MountInfo;
+
+    #[test]
+    fn parse_mountinfo_line() {
+        let line = b"36 35 98:0 /mnt1 /mnt/my\\040disk rw,noatime master:1 - ext3 /dev/root rw";
+        assert_eq!(MountInfo::parse(line),
+                   Some(MountInfo {
+                       mount_point: PathBuf::from("/mnt/my disk"),
+                       source: OsString::from("/dev/root"),
+                       fs_type: String::from("ext3"),
+                   }));
+        assert_eq!(MountInfo::parse(b"garbage"), None);
+    }
+}
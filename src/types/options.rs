@@ -1,11 +1,59 @@
 
 use clap::ArgMatches;
 
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use snapshot::Snapshot;
+
+use libc;
+
+use super::chart::Chart;
+use super::color_by::ColorBy;
+use super::filter::Filter;
+use super::formatter::parse_size;
+use super::graph_style::GraphStyle;
+use super::hard_links::HardLinks;
+use super::output_format::OutputFormat;
 use super::progress_format::ProgressFormat;
+use super::size_mode::SizeMode;
+use super::sort_by::SortBy;
+use super::tree_style::TreeStyle;
+
+#[derive(Debug, Clone)]
+pub struct OptionsDiff {
+    pub old: PathBuf,
+    pub new: PathBuf,
+    pub threshold: u64,
+    pub max_depth: u16,
+    pub human: bool,
+}
 
 #[derive(Debug, Clone, Copy)]
-pub struct OptionsCache {
+pub struct OptionsDu {
     pub enabled: bool,
+    /// Show files too, not only directories
+    pub all: bool,
+    /// Print grand total of all roots
+    pub total: bool,
+    /// Deepest level shown, roots are level 0
+    pub max_depth: Option<u16>,
+    pub block_size: u64,
+    pub human: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OptionsErrors {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptionsHistory {
+    pub db: PathBuf,
+    pub path: PathBuf,
+    pub human: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -13,6 +61,13 @@ pub struct OptionsHuman {
     pub enabled: bool,
 }
 
+#[derive(Debug, Clone)]
+pub struct OptionsOutput {
+    pub format: OutputFormat,
+    /// Write ncdu JSON dump of the tree to file
+    pub ncdu: Option<PathBuf>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct OptionsProgress {
     pub enabled: bool,
@@ -20,33 +75,155 @@ pub struct OptionsProgress {
     pub format: ProgressFormat,
 }
 
+#[derive(Debug, Clone)]
+pub struct OptionsScan {
+    pub threads: usize,
+    pub one_file_system: bool,
+    pub mount_points: bool,
+    pub filter: Filter,
+    pub count_excluded: bool,
+    /// Count symlinks, FIFOs, sockets and devices as files like du does
+    pub special_files: bool,
+    /// Snapshot loaded for `--since`, unchanged directories reuse its listings
+    pub previous: Option<Arc<Snapshot>>,
+}
+
+impl Default for OptionsScan {
+    fn default() -> OptionsScan {
+        OptionsScan {
+            threads: 1,
+            one_file_system: false,
+            mount_points: false,
+            filter: Filter::new(),
+            count_excluded: false,
+            special_files: false,
+            previous: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OptionsSize {
+    pub hard_links: HardLinks,
+    pub mode: SizeMode,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptionsSnapshot {
+    pub save: Option<PathBuf>,
+    pub load: Option<PathBuf>,
+    pub since: Option<PathBuf>,
+    pub db: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OptionsSort {
+    pub by: SortBy,
+    pub reverse: bool,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct OptionsStats {
     pub enabled: bool,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct OptionsSvg {
+    pub chart: Chart,
+    /// Nodes narrower than this many degrees are left out together with their children
+    pub min_angle: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OptionsTop {
+    pub count: usize,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct OptionsTree {
     pub enabled: bool,
     pub max_depth: u16,
     pub only_dirs: bool,
+    pub graph_width: usize,
+    pub graph_style: GraphStyle,
+    pub style: TreeStyle,
+    /// Colour bars, set when stdout is a terminal
+    pub color: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
+pub struct OptionsTreemap {
+    pub output: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub color_by: ColorBy,
+    pub max_depth: u16,
+}
+
+#[derive(Debug, Clone)]
 pub struct Options {
-    pub cache: OptionsCache,
+    /// Set by `browse` subcommand
+    pub browse: bool,
+    pub du: OptionsDu,
+    pub errors: OptionsErrors,
     pub human: OptionsHuman,
+    pub output: OptionsOutput,
     pub progress: OptionsProgress,
+    pub scan: OptionsScan,
+    pub size: OptionsSize,
+    pub snapshot: OptionsSnapshot,
+    pub sort: OptionsSort,
     pub stats: OptionsStats,
+    pub svg: OptionsSvg,
+    pub top: OptionsTop,
     pub tree: OptionsTree,
+    /// Set by `treemap` subcommand
+    pub treemap: Option<OptionsTreemap>,
 }
 
 impl<'a> From<&'a ArgMatches<'a>> for Options {
     fn from(matches: &ArgMatches) -> Options {
         debug!("Parsing options");
         Options {
-            cache: OptionsCache { enabled: matches.is_present("cache") },
+            browse: matches.subcommand_matches("browse").is_some(),
+            du: OptionsDu {
+                enabled: matches.is_present("du"),
+                all: matches.is_present("all"),
+                total: matches.is_present("total"),
+                max_depth: if matches.is_present("summarize") {
+                    Some(0)
+                } else {
+                    matches.value_of("max-depth").and_then(|val| {
+                        let res = val.parse::<u16>().ok();
+                        if res.is_none() {
+                            warn!("Invalid max depth specified - {:?} - showing all levels", val);
+                        }
+                        res
+                    })
+                },
+                block_size: matches.value_of("block-size")
+                    .and_then(|val| {
+                        let res = parse_size(val).and_then(|size| if size > 0 {
+                            Some(size)
+                        } else {
+                            None
+                        });
+                        if res.is_none() {
+                            warn!("Invalid block size specified - {:?} - using 1024", val);
+                        }
+                        res
+                    })
+                    .unwrap_or(1024),
+                human: matches.is_present("human"),
+            },
+            errors: OptionsErrors { enabled: matches.is_present("errors") },
             human: OptionsHuman { enabled: matches.is_present("human") },
+            output: OptionsOutput {
+                format: OutputFormat::from(matches.value_of("format")
+                    .unwrap()
+                    .to_string()),
+                ncdu: matches.value_of_os("export-ncdu").map(PathBuf::from),
+            },
             progress: OptionsProgress {
                 enabled: matches.is_present("progress"),
                 count: matches.value_of("progress-count")
@@ -58,7 +235,66 @@ impl<'a> From<&'a ArgMatches<'a>> for Options {
                     .unwrap()
                     .to_string()),
             },
+            scan: OptionsScan {
+                threads: matches.value_of("threads")
+                    .unwrap()
+                    .to_string()
+                    .parse::<usize>()
+                    .unwrap_or(1),
+                one_file_system: matches.is_present("one-file-system"),
+                mount_points: matches.is_present("mount-points"),
+                filter: filter_from(matches),
+                count_excluded: matches.is_present("count-excluded"),
+                special_files: matches.is_present("du"),
+                previous: None,
+            },
+            size: OptionsSize {
+                hard_links: HardLinks::from(matches.value_of("hard-links")
+                    .unwrap()
+                    .to_string()),
+                // du counts allocated blocks unless told otherwise
+                mode: if matches.is_present("disk-usage") ||
+                         (matches.is_present("du") && !matches.is_present("apparent-size")) {
+                    SizeMode::DiskUsage
+                } else {
+                    SizeMode::Apparent
+                },
+            },
+            snapshot: OptionsSnapshot {
+                save: matches.value_of_os("save").map(PathBuf::from),
+                load: matches.value_of_os("load").map(PathBuf::from),
+                since: matches.value_of_os("since").map(PathBuf::from),
+                db: matches.value_of_os("db").map(PathBuf::from),
+            },
+            sort: OptionsSort {
+                by: SortBy::from(matches.value_of("sort")
+                    .unwrap()
+                    .to_string()),
+                reverse: matches.is_present("reverse"),
+            },
             stats: OptionsStats { enabled: matches.is_present("stats") },
+            svg: OptionsSvg {
+                chart: Chart::from(matches.value_of("chart")
+                    .unwrap()
+                    .to_string()),
+                min_angle: matches.value_of("min-angle")
+                    .unwrap()
+                    .to_string()
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|val| if val >= 0.0 { Some(val) } else { None })
+                    .unwrap_or_else(|| {
+                        warn!("Invalid minimal angle specified - using 0.5");
+                        0.5
+                    }),
+            },
+            top: OptionsTop {
+                count: matches.value_of("top")
+                    .unwrap()
+                    .to_string()
+                    .parse::<usize>()
+                    .unwrap_or(0),
+            },
             tree: OptionsTree {
                 enabled: matches.is_present("tree"),
                 max_depth: matches.value_of("tree-depth")
@@ -67,7 +303,124 @@ impl<'a> From<&'a ArgMatches<'a>> for Options {
                     .parse::<u16>()
                     .unwrap_or(0),
                 only_dirs: matches.is_present("tree-only-dirs"),
+                graph_width: matches.value_of("graph-width")
+                    .unwrap()
+                    .to_string()
+                    .parse::<usize>()
+                    .unwrap_or(0),
+                graph_style: GraphStyle::from(matches.value_of("graph-style")
+                    .unwrap()
+                    .to_string()),
+                style: TreeStyle::from(matches.value_of("tree-style")
+                    .unwrap()
+                    .to_string()),
+                color: unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 },
             },
+            treemap: matches.subcommand_matches("treemap").map(OptionsTreemap::from),
+        }
+    }
+}
+
+impl<'a> From<&'a ArgMatches<'a>> for OptionsDiff {
+    fn from(matches: &ArgMatches) -> OptionsDiff {
+        debug!("Parsing diff options");
+        OptionsDiff {
+            old: PathBuf::from(matches.value_of_os("OLD").unwrap()),
+            new: PathBuf::from(matches.value_of_os("NEW").unwrap()),
+            threshold: matches.value_of("threshold")
+                .and_then(|val| {
+                    let res = parse_size(val);
+                    if res.is_none() {
+                        warn!("Invalid threshold specified - {:?} - using 0", val);
+                    }
+                    res
+                })
+                .unwrap_or(0),
+            max_depth: matches.value_of("tree-depth")
+                .unwrap()
+                .to_string()
+                .parse::<u16>()
+                .unwrap_or(0),
+            human: matches.is_present("human"),
         }
     }
 }
+
+impl<'a> From<&'a ArgMatches<'a>> for OptionsHistory {
+    fn from(matches: &ArgMatches) -> OptionsHistory {
+        debug!("Parsing history options");
+        OptionsHistory {
+            db: PathBuf::from(matches.value_of_os("db").unwrap()),
+            path: trim_path(matches.value_of_os("PATH").unwrap()),
+            human: matches.is_present("human"),
+        }
+    }
+}
+
+impl<'a> From<&'a ArgMatches<'a>> for OptionsTreemap {
+    fn from(matches: &ArgMatches) -> OptionsTreemap {
+        debug!("Parsing treemap options");
+        let dimension = |name: &str, default: u32| {
+            matches.value_of(name)
+                .unwrap()
+                .to_string()
+                .parse::<u32>()
+                .ok()
+                .and_then(|val| if val > 0 { Some(val) } else { None })
+                .unwrap_or_else(|| {
+                    warn!("Invalid {} specified - using {}", name, default);
+                    default
+                })
+        };
+
+        OptionsTreemap {
+            output: PathBuf::from(matches.value_of_os("output").unwrap()),
+            width: dimension("width", 1024),
+            height: dimension("height", 768),
+            color_by: ColorBy::from(matches.value_of("color-by")
+                .unwrap()
+                .to_string()),
+            max_depth: matches.value_of("tree-depth")
+                .unwrap()
+                .to_string()
+                .parse::<u16>()
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Removes trailing slashes, so that paths match the ones recorded by the scanner
+pub fn trim_path(path: &OsStr) -> PathBuf {
+    let mut bytes = path.as_bytes();
+    while bytes.len() > 1 && bytes.ends_with(b"/") {
+        bytes = &bytes[..bytes.len() - 1];
+    }
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+// Rules from files go first, so that command line patterns take precedence
+fn filter_from(matches: &ArgMatches) -> Filter {
+    let mut filter = Filter::new();
+
+    if let Some(files) = matches.values_of("exclude-from") {
+        for file in files {
+            if let Err(e) = filter.exclude_from(file) {
+                warn!("Unable to read exclude file - {:?} - {}", file, e);
+            }
+        }
+    }
+
+    if let Some(patterns) = matches.values_of("exclude") {
+        for pattern in patterns {
+            filter.exclude(pattern);
+        }
+    }
+
+    if let Some(patterns) = matches.values_of("include") {
+        for pattern in patterns {
+            filter.include(pattern);
+        }
+    }
+
+    filter
+}
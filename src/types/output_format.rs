@@ -0,0 +1,44 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Human readable tree, see `--tree`
+    Text,
+    /// Single JSON document with nested nodes
+    Json,
+    /// One JSON object per node and line
+    JsonLines,
+    /// One row per node
+    Csv,
+    Tsv,
+    /// Sunburst or icicle chart
+    Svg,
+    /// Interactive report for browsers
+    Html,
+    /// Stacks for flamegraph tools
+    Folded,
+}
+
+impl From<String> for OutputFormat {
+    fn from(val: String) -> OutputFormat {
+        let val = val.to_lowercase();
+        if val == String::from("text") {
+            OutputFormat::Text
+        } else if val == String::from("json") {
+            OutputFormat::Json
+        } else if val == String::from("json-lines") {
+            OutputFormat::JsonLines
+        } else if val == String::from("csv") {
+            OutputFormat::Csv
+        } else if val == String::from("tsv") {
+            OutputFormat::Tsv
+        } else if val == String::from("svg") {
+            OutputFormat::Svg
+        } else if val == String::from("html") {
+            OutputFormat::Html
+        } else if val == String::from("folded") {
+            OutputFormat::Folded
+        } else {
+            warn!("Invalid output format specified - {:?} - using OutputFormat::Text", val);
+            OutputFormat::Text
+        }
+    }
+}
@@ -1,7 +1,11 @@
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Default)]
 pub struct OverallInfo {
     pub dirs: u64,
     pub files: u64,
+    pub dedup_size: u64,
+    pub errors: u64,
+    /// Directories whose listing was reused from previous snapshot
+    pub reused: u64,
 }
 
 impl OverallInfo {
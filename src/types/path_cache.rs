@@ -1,24 +1,46 @@
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
 use serde::ser::{Serialize, Serializer, SerializeStruct};
 
 use std::cmp::Ordering;
-use std::collections::BTreeMap;
-use std::fmt::Debug;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fmt::{self, Debug};
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
 
 use super::event_type::EventType;
-use super::formatter::human_format;
+use super::formatter::{bar, bar_colour, escape_os_str, human_format_if_needed};
+use super::hard_links::HardLinks;
 use super::item_info::FsItemInfo;
 use super::item_info::ItemSize;
+use super::mount_info::MountInfo;
+use super::options::{OptionsSort, OptionsTree};
+use super::size_mode::SizeMode;
+use super::sort_by::SortBy;
+
+/// Key of the synthetic item holding sizes of excluded entries, no file name can contain NUL
+pub const EXCLUDED_KEY: &'static str = "\0excluded";
+
+/// Name the synthetic item is shown as
+pub const EXCLUDED_NAME: &'static str = "<excluded>";
 
 #[derive(Debug, Clone)]
 pub struct PathCache<T>
     where T: Clone + Copy + Debug + ItemSize + Serialize
 {
-    pub path: String,
+    pub path: OsString,
     pub data: Option<T>,
     pub dirs_size: u64,
     pub files_size: u64,
     pub total_size: u64,
-    pub childs: Option<BTreeMap<String, PathCache<T>>>,
+    pub dedup_size: u64,
+    pub apparent_size: u64,
+    pub disk_size: u64,
+    pub items: u64,
+    pub mount: Option<MountInfo>,
+    pub childs: Option<BTreeMap<OsString, PathCache<T>>>,
 }
 
 impl<T> PathCache<T>
@@ -36,32 +58,143 @@ impl<T> PathCache<T>
         self.total_size
     }
 
+    /// Bytes of hard linked files in this subtree which were not counted again
+    pub fn dedup_size(&self) -> u64 {
+        self.dedup_size
+    }
+
+    /// Apparent size of the subtree regardless of size mode
+    pub fn apparent_size(&self) -> u64 {
+        self.apparent_size
+    }
+
+    /// Allocated size of the subtree regardless of size mode
+    pub fn disk_size(&self) -> u64 {
+        self.disk_size
+    }
+
+    /// Number of files and directories in the subtree, not counting the node itself
+    pub fn items(&self) -> u64 {
+        self.items
+    }
+
+    /// Share of the apparent size which is not backed by allocated blocks. Negative values mean
+    /// more space is allocated than used, typically by small files on large blocks.
+    pub fn sparseness(&self) -> f64 {
+        if self.apparent_size > 0 {
+            1.0 - (self.disk_size as f64 / self.apparent_size as f64)
+        } else {
+            0.0
+        }
+    }
+
     pub fn calculate_size(&mut self) {
+        self.calculate_size_with(HardLinks::Naive, SizeMode::Apparent, &mut HashSet::new());
+    }
+
+    /// Calculates sizes of all trees in `pc`, sharing already counted links between them.
+    pub fn calculate_sizes(pc: &mut BTreeMap<OsString, PathCache<T>>,
+                           hard_links: HardLinks,
+                           size_mode: SizeMode) {
+        let mut seen = HashSet::new();
+        for (k, v) in pc.iter_mut() {
+            debug!("Calculating {:?}", k);
+            v.calculate_size_with(hard_links, size_mode, &mut seen);
+            debug!("Calculated total_size of topmost directory is {:?}",
+                   v.total_size());
+        }
+    }
+
+    /// Calculates sizes counting hard linked files according to `hard_links`, `dirs_size`,
+    /// `files_size`, `total_size` and `dedup_size` are measured according to `size_mode`.
+    ///
+    /// `seen` holds links already counted in `HardLinks::Global` mode. In `HardLinks::Subtree`
+    /// mode links found in this subtree are returned together with their apparent and disk sizes.
+    pub fn calculate_size_with(&mut self,
+                               hard_links: HardLinks,
+                               size_mode: SizeMode,
+                               seen: &mut HashSet<(u64, u64)>)
+                               -> HashMap<(u64, u64), (u64, u64)> {
         self.dirs_size = 0;
         self.files_size = 0;
         self.total_size = 0;
+        self.dedup_size = 0;
+        self.apparent_size = 0;
+        self.disk_size = 0;
+        self.items = 0;
+
+        let pick = |sizes: (u64, u64)| match size_mode {
+            SizeMode::Apparent => sizes.0,
+            SizeMode::DiskUsage => sizes.1,
+        };
+
+        let mut links: HashMap<(u64, u64), (u64, u64)> = HashMap::new();
 
         if self.childs.is_some() {
             for (_k, v) in self.childs.as_mut().unwrap().iter_mut() {
-                v.calculate_size();
+                let child_links = v.calculate_size_with(hard_links, size_mode, seen);
+
+                self.items += v.items;
+                if v.data.is_some() {
+                    self.items += 1;
+                }
 
                 if let Some(data) = v.data {
                     match data.event_type() {
-                        &EventType::File => self.files_size += data.size(),
-                        &EventType::DirEnter => self.dirs_size += v.total_size,
+                        &EventType::File |
+                        &EventType::Excluded => {
+                            let sizes = (data.size(), data.disk_size());
+                            let counted = match (hard_links, data.link_id()) {
+                                (HardLinks::Global, Some(id)) => seen.insert(id),
+                                (HardLinks::Subtree, Some(id)) => {
+                                    links.insert(id, sizes).is_none()
+                                }
+                                _ => true,
+                            };
+
+                            if counted {
+                                self.files_size += pick(sizes);
+                                self.apparent_size += sizes.0;
+                                self.disk_size += sizes.1;
+                            } else {
+                                self.dedup_size += pick(sizes);
+                            }
+                        }
+                        &EventType::DirEnter => {
+                            self.dirs_size += v.total_size;
+                            self.dedup_size += v.dedup_size;
+                            self.apparent_size += v.apparent_size;
+                            self.disk_size += v.disk_size;
+
+                            for (id, sizes) in child_links {
+                                if links.insert(id, sizes).is_some() {
+                                    self.dirs_size -= pick(sizes);
+                                    self.dedup_size += pick(sizes);
+                                    self.apparent_size -= sizes.0;
+                                    self.disk_size -= sizes.1;
+                                }
+                            }
+                        }
                         _ => {}
                     }
+                } else {
+                    // Path prefix above scanned directory, only pass its dedup through
+                    self.dedup_size += v.dedup_size;
                 }
             }
         }
 
         self.total_size = self.dirs_size + self.files_size;
         if let Some(data) = self.data {
-            self.total_size += data.size()
+            self.total_size += size_mode.size(&data);
+            self.apparent_size += data.size();
+            self.disk_size += data.disk_size();
         }
+
+        links
     }
 
-    pub fn construct(pc: &mut BTreeMap<String, PathCache<T>>, parts: &mut Vec<String>, data: &T) {
+    pub fn construct(pc: &mut BTreeMap<OsString, PathCache<T>>, parts: &mut Vec<OsString>, data: &T) {
         if let Some(part) = parts.pop() {
             let node_data = if parts.len() == 0 {
                 Some(data.clone())
@@ -69,7 +202,7 @@ impl<T> PathCache<T>
                 None
             };
 
-            let mut tmp: BTreeMap<String, PathCache<T>> = BTreeMap::new();
+            let mut tmp: BTreeMap<OsString, PathCache<T>> = BTreeMap::new();
             if parts.len() > 0 {
                 PathCache::construct(&mut tmp, parts, data);
             };
@@ -79,15 +212,23 @@ impl<T> PathCache<T>
 
             if has_key {
                 let item = pc.get_mut(&key).unwrap();
+                if node_data.is_some() {
+                    item.data = node_data;
+                }
                 if item.childs == None {
                     item.childs = Some(tmp);
                 } else {
                     PathCache::merge(item.childs.as_mut().unwrap(), &mut tmp);
                 }
             } else {
+                let path = if part == EXCLUDED_KEY {
+                    OsString::from(EXCLUDED_NAME)
+                } else {
+                    part.clone()
+                };
                 pc.insert(key,
                           PathCache {
-                              path: part.clone(),
+                              path: path,
                               childs: if parts.len() > 0 {
                                   Some(tmp)
                               } else {
@@ -97,17 +238,58 @@ impl<T> PathCache<T>
                               dirs_size: 0,
                               files_size: 0,
                               total_size: 0,
+                              dedup_size: 0,
+                              apparent_size: 0,
+                              disk_size: 0,
+                              items: 0,
+                              mount: None,
                           });
             }
         }
     }
 
-    pub fn merge(left: &mut BTreeMap<String, PathCache<T>>,
-                 right: &mut BTreeMap<String, PathCache<T>>) {
+    pub fn find<'a>(pc: &'a BTreeMap<OsString, PathCache<T>>,
+                    parts: &mut Vec<OsString>)
+                    -> Option<&'a PathCache<T>> {
+        match parts.pop() {
+            Some(part) => {
+                let item = pc.get(&part)?;
+                if parts.len() == 0 {
+                    Some(item)
+                } else {
+                    PathCache::find(item.childs.as_ref()?, parts)
+                }
+            }
+            None => None,
+        }
+    }
+
+    pub fn find_mut<'a>(pc: &'a mut BTreeMap<OsString, PathCache<T>>,
+                        parts: &mut Vec<OsString>)
+                        -> Option<&'a mut PathCache<T>> {
+        match parts.pop() {
+            Some(part) => {
+                let item = pc.get_mut(&part)?;
+                if parts.len() == 0 {
+                    Some(item)
+                } else {
+                    PathCache::find_mut(item.childs.as_mut()?, parts)
+                }
+            }
+            None => None,
+        }
+    }
+
+    pub fn merge(left: &mut BTreeMap<OsString, PathCache<T>>,
+                 right: &mut BTreeMap<OsString, PathCache<T>>) {
         for (k, v) in right.iter_mut() {
             if !left.contains_key(k) {
                 left.insert(k.clone(), v.clone());
             } else {
+                if v.data.is_some() {
+                    left.get_mut(k).unwrap().data = v.data;
+                }
+
                 let left_has_childs = left.get(k).as_ref().unwrap().childs.as_ref() != None;
                 let right_has_childs = v.childs != None;
 
@@ -123,53 +305,217 @@ impl<T> PathCache<T>
         }
     }
 
-    pub fn print(pc: &BTreeMap<String, PathCache<T>>,
-                 depth: u16,
-                 max_depth: u16,
-                 only_dirs: bool,
-                 human_readable: bool) {
-        for (_k, ref v) in pc {
-            // print!("{:?}", v);
-
-            if let Some(data) = v.data {
-                match data.event_type() {
-                    &EventType::DirEnter => {
-                        print!("{}", String::from("  ").repeat(depth as usize));
-                        println!("{} ({} / {} / {})",
-                                 v.path,
-                                 human_format_if_needed(v.files_size(), human_readable),
-                                 human_format_if_needed(v.dirs_size(), human_readable),
-                                 human_format_if_needed(v.total_size(), human_readable));
+    /// Nodes of one tree level in the requested order, ties are broken by name
+    pub fn sorted<'a>(pc: &'a BTreeMap<OsString, PathCache<T>>,
+                      sort: &OptionsSort)
+                      -> Vec<&'a PathCache<T>> {
+        let mut res = pc.values().collect::<Vec<_>>();
+        if sort.by != SortBy::Name {
+            res.sort_by(|a, b| PathCache::compare(a, b, sort.by));
+        }
+
+        if sort.reverse {
+            res.reverse();
+        }
+
+        res
+    }
+
+    /// Order of two nodes by `by`, largest, newest or fullest first, without reversing
+    pub fn compare(a: &PathCache<T>, b: &PathCache<T>, by: SortBy) -> Ordering {
+        let mtime = |v: &PathCache<T>| v.data.map(|data| data.mtime()).unwrap_or(0);
+        match by {
+            SortBy::Name => a.path.cmp(&b.path),
+            SortBy::Size => b.total_size.cmp(&a.total_size),
+            SortBy::Mtime => mtime(b).cmp(&mtime(a)),
+            SortBy::Count => b.items.cmp(&a.items),
+        }
+    }
+
+    /// Prints the tree. Every line starts with percentage of parent and root size and
+    /// optionally with a bar proportional to the percentage of parent.
+    pub fn print(pc: &BTreeMap<OsString, PathCache<T>>,
+                 opts: &OptionsTree,
+                 human_readable: bool,
+                 sort: &OptionsSort) {
+        let stdout = io::stdout();
+        let _ = PathCache::write_tree(&mut stdout.lock(), pc, opts, human_readable, sort);
+    }
+
+    pub fn write_tree<W: Write>(out: &mut W,
+                                pc: &BTreeMap<OsString, PathCache<T>>,
+                                opts: &OptionsTree,
+                                human_readable: bool,
+                                sort: &OptionsSort)
+                                -> io::Result<()> {
+        PathCache::write_level(out, pc, 0, "", None, None, opts, human_readable, sort)
+    }
+
+    fn write_level<W: Write>(out: &mut W,
+                             pc: &BTreeMap<OsString, PathCache<T>>,
+                             depth: u16,
+                             prefix: &str,
+                             parent_size: Option<u64>,
+                             root_size: Option<u64>,
+                             opts: &OptionsTree,
+                             human_readable: bool,
+                             sort: &OptionsSort)
+                             -> io::Result<()> {
+        // Hidden nodes are dropped first, so that the last shown one gets the closing connector
+        let visible = PathCache::sorted(pc, sort)
+            .into_iter()
+            .filter(|v| match v.data {
+                Some(data) => {
+                    match data.event_type() {
+                        &EventType::File | &EventType::Excluded => !opts.only_dirs,
+                        &EventType::DirLeave => false,
+                        _ => true,
                     }
-                    &EventType::File => {
-                        if only_dirs == false {
-                            print!("{}", String::from("  ").repeat(depth as usize));
-                            println!("{} ({})",
-                                     v.path,
-                                     human_format_if_needed(data.size(), human_readable));
+                }
+                None => true,
+            })
+            .collect::<Vec<_>>();
+
+        for (i, v) in visible.iter().enumerate() {
+            let last = i + 1 == visible.len();
+
+            // Sizes of scanned directories are relative to themselves, not to path prefixes
+            let (parent_size, root_size) = match v.data {
+                Some(_) => {
+                    (parent_size.unwrap_or(v.total_size()), root_size.unwrap_or(v.total_size()))
+                }
+                None => (0, 0),
+            };
+
+            let columns = if v.data.is_some() {
+                graph_columns(v.total_size(), parent_size, root_size, opts)
+            } else {
+                String::from(" ").repeat(graph_columns_width(opts))
+            };
+            let indent = format!("{}{}", prefix, opts.style.branch(depth, last));
+            let name = escape_os_str(&v.path);
+
+            match v.data.map(|data| *data.event_type()) {
+                Some(EventType::DirEnter) => {
+                    writeln!(out,
+                             "{}{}{} ({} / {} / {}{})",
+                             columns,
+                             indent,
+                             name,
+                             human_format_if_needed(v.files_size(), human_readable),
+                             human_format_if_needed(v.dirs_size(), human_readable),
+                             human_format_if_needed(v.total_size(), human_readable),
+                             sparseness_if_needed(v.sparseness()))?;
+                }
+                Some(EventType::MountPoint) => {
+                    match v.mount {
+                        Some(ref mount) => {
+                            writeln!(out,
+                                     "{}{}{} [{} on {}]",
+                                     columns,
+                                     indent,
+                                     name,
+                                     mount.fs_type,
+                                     escape_os_str(&mount.source))?
                         }
+                        None => writeln!(out, "{}{}{} [mount point]", columns, indent, name)?,
                     }
-                    _ => {}
-                };
-            } else {
-                println!("{}", v.path);
+                }
+                Some(_) => {
+                    writeln!(out,
+                             "{}{}{} ({}{})",
+                             columns,
+                             indent,
+                             name,
+                             human_format_if_needed(v.total_size(), human_readable),
+                             sparseness_if_needed(v.sparseness()))?;
+                }
+                None => writeln!(out, "{}{}{}", columns, indent, name)?,
             }
 
             if v.childs != None {
-                if max_depth == 0 || (depth < max_depth) {
-                    PathCache::print(v.childs.as_ref().unwrap(),
-                                     depth + 1,
-                                     max_depth,
-                                     only_dirs,
-                                     human_readable);
+                if descend(depth, opts.max_depth) {
+                    let (parent, root) = match v.data {
+                        Some(_) => (Some(v.total_size()), Some(root_size)),
+                        None => (None, None),
+                    };
+
+                    PathCache::write_level(out,
+                                           v.childs.as_ref().unwrap(),
+                                           depth + 1,
+                                           &format!("{}{}", prefix, opts.style.pipe(depth, last)),
+                                           parent,
+                                           root,
+                                           opts,
+                                           human_readable,
+                                           sort)?;
                 }
             }
         }
+
+        Ok(())
     }
 }
 
 pub type PathCacheInfo = PathCache<FsItemInfo>;
 
+/// Whether children of a node at `depth` are shown, `max_depth` of 0 means no limit
+pub fn descend(depth: u16, max_depth: u16) -> bool {
+    max_depth == 0 || depth < max_depth
+}
+
+/// Splits `path` on `/` into components in reversed order, as expected by `PathCache::construct`.
+/// Absolute paths start with an empty component.
+pub fn path_parts(path: &Path) -> Vec<OsString> {
+    let bytes = path.as_os_str().as_bytes();
+
+    let mut parts = bytes.split(|b| *b == b'/')
+        .map(|part| OsString::from_vec(part.to_vec()))
+        .collect::<Vec<OsString>>();
+    parts.reverse();
+    parts
+}
+
+// `" 45.20%  12.00% [####      ] "`, the bar is shown only with `graph_width` set
+fn graph_columns(size: u64, parent_size: u64, root_size: u64, opts: &OptionsTree) -> String {
+    let share = |total: u64| if total > 0 {
+        size as f64 / total as f64
+    } else {
+        0.0
+    };
+
+    let mut res = format!("{:>7.2}% {:>7.2}% ",
+                          share(parent_size) * 100.0,
+                          share(root_size) * 100.0);
+
+    if opts.graph_width > 0 {
+        let bar = bar(share(parent_size), opts.graph_width, opts.graph_style);
+        if opts.color {
+            res.push_str(&format!("[{}] ", bar_colour(share(parent_size)).paint(bar)));
+        } else {
+            res.push_str(&format!("[{}] ", bar));
+        }
+    }
+
+    res
+}
+
+fn graph_columns_width(opts: &OptionsTree) -> usize {
+    if opts.graph_width > 0 {
+        18 + opts.graph_width + 3
+    } else {
+        18
+    }
+}
+
+fn sparseness_if_needed(sparseness: f64) -> String {
+    if sparseness > 0.0 {
+        format!(", {:.2}% sparse", sparseness * 100.0)
+    } else {
+        String::new()
+    }
+}
+
 impl<T> Eq for PathCache<T> where T: Clone + Copy + Debug + ItemSize + Serialize {}
 
 impl<T> Ord for PathCache<T>
@@ -202,29 +548,447 @@ impl<T> Serialize for PathCache<T>
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where S: Serializer
     {
-        let mut s = serializer.serialize_struct("PathCache", 5)?;
-        s.serialize_field("path", &self.path)?;
+        let mut s = serializer.serialize_struct("PathCache", 11)?;
+        s.serialize_field("path", &self.path.as_bytes())?;
         s.serialize_field("data", &self.data)?;
         s.serialize_field("dirs_size", &self.dirs_size)?;
         s.serialize_field("files_size", &self.files_size)?;
         s.serialize_field("total_size", &self.total_size)?;
-        s.serialize_field("childs", &self.childs)?;
+        s.serialize_field("dedup_size", &self.dedup_size)?;
+        s.serialize_field("apparent_size", &self.apparent_size)?;
+        s.serialize_field("disk_size", &self.disk_size)?;
+        s.serialize_field("items", &self.items)?;
+        s.serialize_field("mount", &self.mount)?;
+        s.serialize_field("childs", &self.childs.as_ref().map(Childs))?;
         s.end()
     }
 }
 
+// Child nodes are written as a sequence, their keys are the node paths. Maps with
+// byte string keys can not be represented in formats like JSON.
+struct Childs<'a, T>(&'a BTreeMap<OsString, PathCache<T>>)
+    where T: 'a + Clone + Copy + Debug + ItemSize + Serialize;
 
-fn human_format_if_needed(size: u64, human_readable: bool) -> String {
-    match human_readable {
-        true => {
-            let (val, unit) = human_format(size as f32);
-            if val == val.floor() {
-                format!("{}{}B", val as u64, unit)
-            } else {
-                format!("{:.2}{}B", val, unit)
+impl<'a, T> Serialize for Childs<'a, T>
+    where T: Clone + Copy + Debug + ItemSize + Serialize
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.collect_seq(self.0.values())
+    }
+}
+
+const FIELDS: &'static [&'static str] = &["path",
+                                          "data",
+                                          "dirs_size",
+                                          "files_size",
+                                          "total_size",
+                                          "dedup_size",
+                                          "apparent_size",
+                                          "disk_size",
+                                          "items",
+                                          "mount",
+                                          "childs"];
+
+impl<'de, T> Deserialize<'de> for PathCache<T>
+    where T: Clone + Copy + Debug + ItemSize + Serialize + Deserialize<'de>
+{
+    fn deserialize<D>(deserializer: D) -> Result<PathCache<T>, D::Error>
+        where D: Deserializer<'de>
+    {
+        deserializer.deserialize_struct("PathCache", FIELDS, PathCacheVisitor(PhantomData))
+    }
+}
+
+struct PathCacheVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for PathCacheVisitor<T>
+    where T: Clone + Copy + Debug + ItemSize + Serialize + Deserialize<'de>
+{
+    type Value = PathCache<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("struct PathCache")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<PathCache<T>, A::Error>
+        where A: SeqAccess<'de>
+    {
+        macro_rules! next {
+            ($i:expr) => (seq.next_element()?.ok_or_else(|| de::Error::invalid_length($i, &self))?)
+        }
+
+        let path: Vec<u8> = next!(0);
+        let data = next!(1);
+        let dirs_size = next!(2);
+        let files_size = next!(3);
+        let total_size = next!(4);
+        let dedup_size = next!(5);
+        let apparent_size = next!(6);
+        let disk_size = next!(7);
+        let items = next!(8);
+        let mount = next!(9);
+        let childs: Option<Vec<PathCache<T>>> = next!(10);
+
+        Ok(PathCache {
+            path: OsString::from_vec(path),
+            data: data,
+            dirs_size: dirs_size,
+            files_size: files_size,
+            total_size: total_size,
+            dedup_size: dedup_size,
+            apparent_size: apparent_size,
+            disk_size: disk_size,
+            items: items,
+            mount: mount,
+            childs: childs.map(childs_map),
+        })
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<PathCache<T>, A::Error>
+        where A: MapAccess<'de>
+    {
+        let mut path: Option<Vec<u8>> = None;
+        let mut data = None;
+        let (mut dirs_size, mut files_size, mut total_size) = (None, None, None);
+        let (mut dedup_size, mut apparent_size, mut disk_size) = (None, None, None);
+        let mut items = None;
+        let mut mount = None;
+        let mut childs: Option<Option<Vec<PathCache<T>>>> = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "path" => path = Some(map.next_value()?),
+                "data" => data = Some(map.next_value()?),
+                "dirs_size" => dirs_size = Some(map.next_value()?),
+                "files_size" => files_size = Some(map.next_value()?),
+                "total_size" => total_size = Some(map.next_value()?),
+                "dedup_size" => dedup_size = Some(map.next_value()?),
+                "apparent_size" => apparent_size = Some(map.next_value()?),
+                "disk_size" => disk_size = Some(map.next_value()?),
+                "items" => items = Some(map.next_value()?),
+                "mount" => mount = Some(map.next_value()?),
+                "childs" => childs = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let path = path.ok_or_else(|| de::Error::missing_field("path"))?;
+
+        Ok(PathCache {
+            path: OsString::from_vec(path),
+            data: data.unwrap_or(None),
+            dirs_size: dirs_size.unwrap_or(0),
+            files_size: files_size.unwrap_or(0),
+            total_size: total_size.unwrap_or(0),
+            dedup_size: dedup_size.unwrap_or(0),
+            apparent_size: apparent_size.unwrap_or(0),
+            disk_size: disk_size.unwrap_or(0),
+            items: items.unwrap_or(0),
+            mount: mount.unwrap_or(None),
+            childs: childs.unwrap_or(None).map(childs_map),
+        })
+    }
+}
+
+fn childs_map<T>(childs: Vec<PathCache<T>>) -> BTreeMap<OsString, PathCache<T>>
+    where T: Clone + Copy + Debug + ItemSize + Serialize
+{
+    childs.into_iter()
+        .map(|child| {
+            // Synthetic item of excluded sizes is shown under a name a real file can have
+            let key = match child.data.as_ref().map(|data| data.event_type()) {
+                Some(&EventType::Excluded) if child.path == EXCLUDED_NAME => {
+                    OsString::from(EXCLUDED_KEY)
+                }
+                _ => child.path.clone(),
+            };
+            (key, child)
+        })
+        .collect()
+}
+
+
+/// Trees for tests of modules working with `PathCache`
+#[cfg(test)]
+pub mod fixture {
+    use std::collections::BTreeMap;
+    use std::ffi::OsString;
+    use std::path::Path;
+
+    use super::*;
+    use types::event_type::EventType;
+    use types::hard_links::HardLinks;
+    use types::item_info::FsItemInfo;
+    use types::size_mode::SizeMode;
+
+    pub fn item(event_type: EventType, ino: u64, nlink: u64, size: u64) -> FsItemInfo {
+        FsItemInfo {
+            event_type: event_type,
+            dev: 1,
+            ino: ino,
+            mtime: 0,
+            nlink: nlink,
+            size: size,
+            blocks: (size + 511) / 512,
+        }
+    }
+
+    pub fn insert(pc: &mut BTreeMap<OsString, PathCacheInfo>, path: &str, info: FsItemInfo) {
+        PathCache::construct(pc, &mut path_parts(Path::new(path)), &info);
+    }
+
+    /// Tree of `(path, type, size)` items with calculated apparent sizes, the item at index `i`
+    /// has inode and mtime `i`
+    pub fn tree(items: &[(&str, EventType, u64)]) -> BTreeMap<OsString, PathCacheInfo> {
+        let mut pc = BTreeMap::new();
+        for (i, &(path, event_type, size)) in items.iter().enumerate() {
+            let mut info = item(event_type, i as u64, 1, size);
+            info.mtime = i as i64;
+            insert(&mut pc, path, info);
+        }
+        PathCache::calculate_sizes(&mut pc, HardLinks::Global, SizeMode::Apparent);
+        pc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bincode::{deserialize, serialize, Infinite};
+    use serde_json;
+
+    use std::collections::BTreeMap;
+    use std::ffi::{OsStr, OsString};
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use super::fixture::{insert, item};
+    use types::event_type::EventType;
+    use types::hard_links::HardLinks;
+    use types::size_mode::SizeMode;
+
+    // root/{a/x, a/y, b/z} where x, y and z are links to one 100 byte inode
+    fn linked_tree() -> BTreeMap<OsString, PathCacheInfo> {
+        let mut pc = BTreeMap::new();
+        insert(&mut pc, "root", item(EventType::DirEnter, 1, 2, 0));
+        insert(&mut pc, "root/a", item(EventType::DirEnter, 2, 2, 0));
+        insert(&mut pc, "root/b", item(EventType::DirEnter, 3, 2, 0));
+        insert(&mut pc, "root/a/x", item(EventType::File, 10, 3, 100));
+        insert(&mut pc, "root/a/y", item(EventType::File, 10, 3, 100));
+        insert(&mut pc, "root/b/z", item(EventType::File, 10, 3, 100));
+        pc
+    }
+
+    fn sizes(pc: &BTreeMap<OsString, PathCacheInfo>) -> (u64, u64, u64, u64) {
+        let root = &pc[OsStr::new("root")];
+        let childs = root.childs.as_ref().unwrap();
+        (root.total_size(), childs[OsStr::new("a")].total_size(), childs[OsStr::new("b")].total_size(), root.dedup_size())
+    }
+
+    #[test]
+    fn hard_links_naive() {
+        let mut pc = linked_tree();
+        PathCache::calculate_sizes(&mut pc, HardLinks::Naive, SizeMode::Apparent);
+        assert_eq!(sizes(&pc), (300, 200, 100, 0));
+    }
+
+    #[test]
+    fn hard_links_global() {
+        let mut pc = linked_tree();
+        PathCache::calculate_sizes(&mut pc, HardLinks::Global, SizeMode::Apparent);
+        assert_eq!(sizes(&pc), (100, 100, 0, 200));
+    }
+
+    #[test]
+    fn hard_links_subtree() {
+        let mut pc = linked_tree();
+        PathCache::calculate_sizes(&mut pc, HardLinks::Subtree, SizeMode::Apparent);
+        assert_eq!(sizes(&pc), (100, 100, 100, 200));
+    }
+
+    #[test]
+    fn sorted_levels() {
+        use types::options::OptionsSort;
+        use types::sort_by::SortBy;
+
+        let mut pc = BTreeMap::new();
+        insert(&mut pc, "/r", item(EventType::DirEnter, 1, 1, 0));
+        insert(&mut pc, "/r/a", item(EventType::File, 2, 1, 10));
+        insert(&mut pc, "/r/b", item(EventType::DirEnter, 3, 1, 0));
+        insert(&mut pc, "/r/b/x", item(EventType::File, 4, 1, 1));
+        insert(&mut pc, "/r/b/y", item(EventType::File, 5, 1, 2));
+        insert(&mut pc, "/r/c", item(EventType::File, 6, 1, 5));
+        PathCache::find_mut(&mut pc, &mut path_parts(Path::new("/r/c")))
+            .unwrap()
+            .data
+            .as_mut()
+            .unwrap()
+            .mtime = 9;
+        PathCache::calculate_sizes(&mut pc, HardLinks::Naive, SizeMode::Apparent);
+
+        let r = &pc[OsStr::new("")].childs.as_ref().unwrap()[OsStr::new("r")];
+        assert_eq!(r.items(), 5);
+
+        let names = |by: SortBy, reverse: bool| {
+            PathCache::sorted(r.childs.as_ref().unwrap(),
+                              &OptionsSort {
+                                  by: by,
+                                  reverse: reverse,
+                              })
+                .iter()
+                .map(|v| v.path.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(SortBy::Name, false), vec!["a", "b", "c"]);
+        assert_eq!(names(SortBy::Name, true), vec!["c", "b", "a"]);
+        assert_eq!(names(SortBy::Size, false), vec!["a", "c", "b"]);
+        assert_eq!(names(SortBy::Size, true), vec!["b", "c", "a"]);
+        assert_eq!(names(SortBy::Mtime, false), vec!["c", "a", "b"]);
+        assert_eq!(names(SortBy::Count, false), vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn tree_connectors() {
+        use types::graph_style::GraphStyle;
+        use types::options::{OptionsSort, OptionsTree};
+        use types::sort_by::SortBy;
+        use types::tree_style::TreeStyle;
+
+        let mut pc = BTreeMap::new();
+        insert(&mut pc, "r", item(EventType::DirEnter, 1, 1, 0));
+        insert(&mut pc, "r/a", item(EventType::DirEnter, 2, 1, 0));
+        insert(&mut pc, "r/a/x", item(EventType::File, 3, 1, 1));
+        insert(&mut pc, "r/a/y", item(EventType::DirEnter, 4, 1, 0));
+        insert(&mut pc, "r/a/y/z", item(EventType::DirEnter, 5, 1, 0));
+        insert(&mut pc, "r/b", item(EventType::DirEnter, 6, 1, 0));
+        insert(&mut pc, "r/c", item(EventType::File, 7, 1, 2));
+        PathCache::calculate_sizes(&mut pc, HardLinks::Naive, SizeMode::Apparent);
+
+        let render = |style: TreeStyle, max_depth: u16, only_dirs: bool| {
+            let opts = OptionsTree {
+                enabled: true,
+                max_depth: max_depth,
+                only_dirs: only_dirs,
+                graph_width: 0,
+                graph_style: GraphStyle::Ascii,
+                style: style,
+                color: false,
+            };
+            let sort = OptionsSort {
+                by: SortBy::Name,
+                reverse: false,
+            };
+
+            let mut out = Vec::new();
+            PathCache::write_tree(&mut out, &pc, &opts, false, &sort).unwrap();
+            String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .map(|l| l[graph_columns_width(&opts)..].split(" (").next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(render(TreeStyle::Unicode, 0, false),
+                   vec!["r", "├── a", "│   ├── x", "│   └── y", "│       └── z", "├── b", "└── c"]);
+        assert_eq!(render(TreeStyle::Unicode, 0, true),
+                   vec!["r", "├── a", "│   └── y", "│       └── z", "└── b"]);
+        assert_eq!(render(TreeStyle::Ascii, 2, true),
+                   vec!["r", "|-- a", "|   `-- y", "`-- b"]);
+        assert_eq!(render(TreeStyle::Indent, 0, true),
+                   vec!["r", "  a", "    y", "      z", "  b"]);
+    }
+
+    #[test]
+    fn disk_usage_and_sparseness() {
+        let mut pc = BTreeMap::new();
+        insert(&mut pc, "root", item(EventType::DirEnter, 1, 2, 0));
+        insert(&mut pc, "root/small", item(EventType::File, 2, 1, 10));
+
+        let mut sparse = item(EventType::File, 3, 1, 1 << 20);
+        sparse.blocks = 8;
+        insert(&mut pc, "root/sparse", sparse);
+
+        PathCache::calculate_sizes(&mut pc, HardLinks::Global, SizeMode::DiskUsage);
+
+        let root = &pc[OsStr::new("root")];
+        let childs = root.childs.as_ref().unwrap();
+        assert_eq!(root.total_size(), 512 + 4096);
+        assert_eq!(root.apparent_size(), 10 + (1 << 20));
+        assert_eq!(childs[OsStr::new("small")].sparseness(), 1.0 - 51.2);
+        assert!(childs[OsStr::new("sparse")].sparseness() > 0.99);
+    }
+
+    #[test]
+    fn non_utf8_components() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"/root/caf\xe9"));
+        let mut parts = path_parts(path);
+        assert_eq!(parts,
+                   vec![OsString::from_vec(b"caf\xe9".to_vec()),
+                        OsString::from("root"),
+                        OsString::new()]);
+
+        let mut pc = BTreeMap::new();
+        PathCache::construct(&mut pc,
+                             &mut parts.clone(),
+                             &item(EventType::File, 1, 1, 10));
+        assert_eq!(PathCache::find_mut(&mut pc, &mut parts).unwrap().path.as_bytes(),
+                   b"caf\xe9");
+    }
+
+    // Tree of files with arbitrary names, nested into `/r/<dir>/` by the first name byte
+    fn arbitrary_tree(files: Vec<(Vec<u8>, u64)>) -> BTreeMap<OsString, PathCacheInfo> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut pc = BTreeMap::new();
+        insert(&mut pc, "/r", item(EventType::DirEnter, 1, 1, 4096));
+
+        for (i, (name, size)) in files.into_iter().enumerate() {
+            let name = name.into_iter().filter(|b| *b != b'/').collect::<Vec<u8>>();
+            if name.is_empty() {
+                continue;
             }
 
+            // Sums of sizes and block counts must not overflow
+            let size = size % (1 << 40);
+            let mut path = format!("/r/{}/", name[0] % 3).into_bytes();
+            path.extend_from_slice(&name);
+            PathCache::construct(&mut pc,
+                                 &mut path_parts(Path::new(OsStr::from_bytes(&path))),
+                                 &item(EventType::File, i as u64 + 2, 1 + (i % 2) as u64, size));
+        }
+
+        PathCache::find_mut(&mut pc, &mut path_parts(Path::new("/r"))).unwrap().mount =
+            Some(MountInfo {
+                mount_point: PathBuf::from("/r"),
+                source: OsString::from("/dev/sda1"),
+                fs_type: String::from("ext4"),
+            });
+
+        PathCache::calculate_sizes(&mut pc, HardLinks::Global, SizeMode::Apparent);
+        pc
+    }
+
+    quickcheck! {
+        fn roundtrip_bincode(files: Vec<(Vec<u8>, u64)>) -> bool {
+            let pc = arbitrary_tree(files);
+            let encoded = serialize(&pc, Infinite).unwrap();
+            let decoded: BTreeMap<OsString, PathCacheInfo> = deserialize(&encoded).unwrap();
+            serialize(&decoded, Infinite).unwrap() == encoded
+        }
+
+        fn roundtrip_json(files: Vec<(Vec<u8>, u64)>) -> bool {
+            let pc = arbitrary_tree(files);
+            let root = &pc[OsStr::new("")];
+            let encoded = serde_json::to_string(root).unwrap();
+            let decoded: PathCacheInfo = serde_json::from_str(&encoded).unwrap();
+            serde_json::to_string(&decoded).unwrap() == encoded &&
+            decoded.total_size() == root.total_size()
         }
-        false => format!("{}", size),
     }
 }
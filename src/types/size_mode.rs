@@ -0,0 +1,18 @@
+use super::item_info::ItemSize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SizeMode {
+    /// Size as reported by `st_size`
+    Apparent,
+    /// Allocated blocks as reported by `st_blocks`
+    DiskUsage,
+}
+
+impl SizeMode {
+    pub fn size<T: ItemSize>(&self, item: &T) -> u64 {
+        match *self {
+            SizeMode::Apparent => item.size(),
+            SizeMode::DiskUsage => item.disk_size(),
+        }
+    }
+}
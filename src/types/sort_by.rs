@@ -0,0 +1,29 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortBy {
+    /// Byte order of names, ascending
+    Name,
+    /// Total size, biggest first
+    Size,
+    /// Modification time, newest first
+    Mtime,
+    /// Number of items in subtree, most first
+    Count,
+}
+
+impl From<String> for SortBy {
+    fn from(val: String) -> SortBy {
+        let val = val.to_lowercase();
+        if val == String::from("name") {
+            SortBy::Name
+        } else if val == String::from("size") {
+            SortBy::Size
+        } else if val == String::from("mtime") {
+            SortBy::Mtime
+        } else if val == String::from("count") {
+            SortBy::Count
+        } else {
+            warn!("Invalid sort specified - {:?} - using SortBy::Name", val);
+            SortBy::Name
+        }
+    }
+}
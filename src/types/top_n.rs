@@ -0,0 +1,69 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::{Path, PathBuf};
+
+/// Keeps `limit` biggest items seen so far, using min-heap of fixed size
+#[derive(Debug, Clone)]
+pub struct TopN {
+    limit: usize,
+    heap: BinaryHeap<Reverse<(u64, PathBuf)>>,
+}
+
+impl TopN {
+    pub fn new(limit: usize) -> TopN {
+        TopN {
+            limit: limit,
+            heap: BinaryHeap::with_capacity(limit + 1),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Path is copied only if the item makes it into the heap
+    pub fn push(&mut self, size: u64, path: &Path) {
+        if self.limit == 0 {
+            return;
+        }
+
+        if self.heap.len() == self.limit {
+            match self.heap.peek() {
+                Some(&Reverse((min, _))) if size <= min => return,
+                _ => {}
+            }
+            self.heap.pop();
+        }
+
+        self.heap.push(Reverse((size, path.to_path_buf())));
+    }
+
+    /// Items from the biggest, equal sizes ordered by path
+    pub fn sorted(&self) -> Vec<(u64, PathBuf)> {
+        let mut res = self.heap.iter().map(|&Reverse(ref item)| item.clone()).collect::<Vec<_>>();
+        res.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::TopN;
+
+    #[test]
+    fn keeps_biggest() {
+        let mut top = TopN::new(3);
+        for &(size, path) in &[(5, "e"), (1, "a"), (9, "i"), (3, "c"), (9, "h"), (2, "b")] {
+            top.push(size, Path::new(path));
+        }
+
+        assert_eq!(top.sorted(),
+                   vec![(9, PathBuf::from("h")), (9, PathBuf::from("i")), (5, PathBuf::from("e"))]);
+
+        let mut none = TopN::new(0);
+        none.push(1, Path::new("a"));
+        assert!(none.is_empty());
+    }
+}
@@ -0,0 +1,56 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TreeStyle {
+    /// `|--` and `` `-- `` connectors
+    Ascii,
+    /// `├──` and `└──` connectors like `tree(1)`
+    Unicode,
+    /// Two spaces per level
+    Indent,
+}
+
+impl TreeStyle {
+    /// Drawn in front of a node, nodes of the first level have no connector
+    pub fn branch(&self, depth: u16, last: bool) -> &'static str {
+        if depth == 0 {
+            return "";
+        }
+
+        match (*self, last) {
+            (TreeStyle::Ascii, false) => "|-- ",
+            (TreeStyle::Ascii, true) => "`-- ",
+            (TreeStyle::Unicode, false) => "├── ",
+            (TreeStyle::Unicode, true) => "└── ",
+            (TreeStyle::Indent, _) => "  ",
+        }
+    }
+
+    /// Drawn in front of descendants of a node, continuing the line of its siblings below
+    pub fn pipe(&self, depth: u16, last: bool) -> &'static str {
+        if depth == 0 {
+            return "";
+        }
+
+        match (*self, last) {
+            (TreeStyle::Ascii, false) => "|   ",
+            (TreeStyle::Unicode, false) => "│   ",
+            (TreeStyle::Indent, _) => "  ",
+            (_, true) => "    ",
+        }
+    }
+}
+
+impl From<String> for TreeStyle {
+    fn from(val: String) -> TreeStyle {
+        let val = val.to_lowercase();
+        if val == String::from("ascii") {
+            TreeStyle::Ascii
+        } else if val == String::from("unicode") {
+            TreeStyle::Unicode
+        } else if val == String::from("indent") {
+            TreeStyle::Indent
+        } else {
+            warn!("Invalid tree style specified - {:?} - using TreeStyle::Indent", val);
+            TreeStyle::Indent
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Work-stealing parallel directory walker.
+//!
+//! Every directory is listed by a single worker which sends `DirEnter`, the files
+//! directly inside the directory and `DirLeave` in this order. Subdirectories are
+//! queued instead of being descended into, so events of different directories
+//! interleave and `DirLeave` of a directory does not wait for its subtree.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use super::TxChannel;
+use types::options::OptionsScan;
+
+// Directory to scan together with the root it was found under
+type Job = (Arc<PathBuf>, PathBuf);
+
+struct Shared {
+    queues: Vec<Mutex<VecDeque<Job>>>,
+    pending: AtomicUsize,
+    idle: Mutex<()>,
+    wakeup: Condvar,
+}
+
+impl Shared {
+    fn new(threads: usize) -> Shared {
+        Shared {
+            queues: (0..threads).map(|_| Mutex::new(VecDeque::new())).collect(),
+            pending: AtomicUsize::new(0),
+            idle: Mutex::new(()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    fn push(&self, id: usize, job: Job) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.queues[id].lock().unwrap().push_back(job);
+        self.wakeup.notify_one();
+    }
+
+    // Own queue is used as a stack (depth first), others are robbed from the front.
+    fn pop(&self, id: usize) -> Option<Job> {
+        if let Some(job) = self.queues[id].lock().unwrap().pop_back() {
+            return Some(job);
+        }
+
+        let count = self.queues.len();
+        for i in 1..count {
+            if let Some(job) = self.queues[(id + i) % count].lock().unwrap().pop_front() {
+                debug!("Worker {} stole {:?}", id, job.1);
+                return Some(job);
+            }
+        }
+
+        None
+    }
+
+    fn done(&self) {
+        if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.wakeup.notify_all();
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.pending.load(Ordering::SeqCst) == 0
+    }
+
+    fn wait(&self) {
+        let guard = self.idle.lock().unwrap();
+        let _ = self.wakeup.wait_timeout(guard, Duration::from_millis(10)).unwrap();
+    }
+}
+
+pub fn walk(tx: &TxChannel, dirs: &Vec<PathBuf>, opts: &OptionsScan) {
+    let threads = if opts.threads > 0 { opts.threads } else { 1 };
+    let shared = Arc::new(Shared::new(threads));
+
+    for (i, dir) in dirs.iter().enumerate() {
+        shared.push(i % threads, (Arc::new(dir.clone()), dir.clone()));
+    }
+
+    let handles = (0..threads)
+        .map(|id| {
+            let tx = tx.clone();
+            let shared = shared.clone();
+            let opts = opts.clone();
+            thread::spawn(move || worker(id, &tx, &shared, &opts))
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        let _ = handle.join();
+    }
+}
+
+fn worker(id: usize, tx: &TxChannel, shared: &Shared, opts: &OptionsScan) {
+    debug!("Worker {} started", id);
+
+    loop {
+        match shared.pop(id) {
+            Some((root, dir)) => {
+                let _ = super::scan_dir(tx, &root, &dir, opts, &mut |path: &Path| {
+                    shared.push(id, (root.clone(), path.to_path_buf()))
+                });
+                shared.done();
+            }
+            None => {
+                if shared.finished() {
+                    break;
+                }
+                shared.wait();
+            }
+        }
+    }
+
+    debug!("Worker {} finished", id);
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use std::collections::BTreeSet;
+    use std::fs::{self, File};
+    use std::sync::mpsc;
+
+    use types::event_type::EventType;
+    use types::message_type::MessageType;
+    use types::options::OptionsScan;
+
+    fn collect<F>(f: F) -> BTreeSet<String>
+        where F: FnOnce(&::TxChannel)
+    {
+        let (tx, rx) = mpsc::channel();
+        f(&tx);
+        drop(tx);
+
+        rx.iter()
+            .map(|(msg, path, info)| {
+                let kind = match (msg, info) {
+                    (MessageType::Subdir, _) => "subdir",
+                    (_, Some(info)) => {
+                        match info.event_type {
+                            EventType::DirEnter => "enter",
+                            EventType::DirLeave => "leave",
+                            EventType::File => "file",
+                            EventType::MountPoint => "mount",
+                            EventType::Excluded => "excluded",
+                        }
+                    }
+                    (msg, None) => panic!("Unexpected message {:?}", msg),
+                };
+                format!("{} {}", kind, path.unwrap().display())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parallel_walk_matches_sequential() {
+        let tmp = tempfile::tempdir().unwrap();
+        for dir in &["a/b/c", "a/d", "e"] {
+            fs::create_dir_all(tmp.path().join(dir)).unwrap();
+        }
+        for file in &["x", "a/y", "a/b/c/z", "a/d/w", "e/v"] {
+            File::create(tmp.path().join(file)).unwrap();
+        }
+
+        let opts = OptionsScan { threads: 4, ..OptionsScan::default() };
+
+        let root = tmp.path().to_path_buf();
+        let sequential = collect(|tx| {
+            let _ = ::visit_dir(tx, &root, &opts);
+        });
+        let parallel = collect(|tx| super::walk(tx, &vec![root.clone()], &opts));
+
+        // 6 directories entered and left, 5 of them announced by their parents, 5 files
+        assert_eq!(sequential.len(), 22);
+        assert_eq!(sequential, parallel);
+    }
+}